@@ -1,7 +1,9 @@
 use std::collections::HashSet;
 
+use async_trait::async_trait;
+
 use crate::components::{ComponentId, InternalId, WebPage};
-use crate::request::cycle::{HandlerResult, RedirectAction, RequestCycle, SessionProvider};
+use crate::request::cycle::{HandlerResult, RedirectAction, RequestCycle};
 use crate::request::handler::RedirectHandler;
 use crate::request::{RequestHandler, Response};
 
@@ -37,12 +39,9 @@ pub fn test(response: &mut Response) -> std::io::Result<HandlerResult> {
         .map(|_| HandlerResult::Complete)
 }
 
+#[async_trait]
 impl<'a> RequestHandler for AjaxRequestTarget<'a> {
-    fn respond(
-        &self,
-        cycle: &mut RequestCycle,
-        _session_provider: &mut SessionProvider,
-    ) -> std::io::Result<HandlerResult> {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
         let some_page = self.get_response_page();
 
         let RequestCycle { response, .. } = cycle;
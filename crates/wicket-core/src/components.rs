@@ -1,16 +1,18 @@
 use std::io::Write;
 use std::{collections::HashMap, fmt::Display};
 
+use async_trait::async_trait;
 use dyn_clone::{clone_trait_object, DynClone};
 use wicket_request::request::mapper::parameter::PageParameters;
 
 use crate::markup::loader::MarkupResourceLocationUtil;
 use crate::markup::MarkupResource;
-use crate::request::cycle::RedirectAction;
+use crate::request::cycle::{RedirectAction, RequestCycle};
 use crate::request::Response;
 
-/// Component needs clone for session page caching.
-pub trait Component: DynClone {
+/// Component needs clone for session page caching, and Send + Sync as the session
+/// pages are shared by the threads of the runtime.
+pub trait Component: DynClone + Send + Sync {
     fn markup_id(&self) -> &str;
     fn set_internal_id(&self, id: InternalId);
     fn get_internal_id(&self) -> Option<InternalId>;
@@ -30,29 +32,70 @@ clone_trait_object!(Component);
 ///     construction of the component tree.
 /// **For Standard Containers:** The identifier is used as a lookup key
 ///     to retrieve pre-parsed markup from the cache.
-pub trait MarkupContainer: MarkupIdentifier + MarkupResourceLocationUtil + MarkupLookup {
+#[async_trait]
+pub trait MarkupContainer:
+    MarkupIdentifier + MarkupResourceLocationUtil + MarkupLookup + Send + Sync
+{
     ///  Render the child component from create or ajax context.
     fn render_component(
         &self,
         id: ComponentId,
         response: &mut Response,
     ) -> std::io::Result<RedirectAction>;
+
+    /// Invoke the listener of the component at the ':' separated path eg "form:submit",
+    /// the target of a listener url "?3-1.submit-form-submit". The cycle gives access to
+    /// the request parameters and the session eg to store the user of a login form, and
+    /// may await eg a database call. Implement with `#[async_trait]`.
+    async fn invoke_listener(
+        &mut self,
+        component_path: &str,
+        listener: &str,
+        _cycle: &mut RequestCycle,
+    ) -> std::io::Result<RedirectAction> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "No listener {} for component {} of {}.",
+                listener,
+                component_path,
+                self.get_markup_identity().name
+            ),
+        ))
+    }
 }
 
 /// Obtain the html file for the given dimensions.
-/// TODO: lookup_markup is macro generated mixing the access of the static file strings
-/// with the dimension match logic. Put the dimension match logic here as a default
-/// method and create a new function exposing the static file data.
 /// TODO integrate MarkupLookup with FileResourceStreamLocator and implement compile time embedded
 /// assets.
 pub trait MarkupLookup {
+    /// The html files of the component sorted by specificity, the most specific first.
+    /// Implemented by the wicket_page macro from the files discovered at compile time.
+    fn markup_resources(&self) -> &[MarkupResource];
+
+    /// The most specific html file for the dimensions, falling back in Wicket order:
+    /// variation, style, lang_COUNTRY, lang and then the default file. A file matches
+    /// when each of its dimensions is unset or equal to the requested one, eg for style
+    /// dark and locale fr_CA the first of `Page.dark.fr.CA.html`, `Page.dark.fr.html`,
+    /// `Page.dark.html`, `Page.fr.CA.html`, `Page.fr.html` and `Page.html`.
     fn lookup_markup(
         &self,
         style: Option<u8>,
         variation: Option<u8>,
         lang: Option<u8>,
         country: Option<u8>,
-    ) -> Option<&MarkupResource>;
+    ) -> Option<&MarkupResource> {
+        let matches = |dimension: Option<u8>, requested: Option<u8>| {
+            dimension.is_none() || dimension == requested
+        };
+        // The resources are pre-sorted, so the first match is the most specific.
+        self.markup_resources().iter().find(|r| {
+            matches(r.style, style)
+                && matches(r.variation, variation)
+                && matches(r.lang, lang)
+                && matches(r.country, country)
+        })
+    }
 }
 
 #[derive(Default)]
@@ -74,13 +117,19 @@ pub trait MarkupIdentifier {
     fn get_markup_identity(&self) -> &MarkupType;
 }
 
+/// Type level access to the MarkupType eg for mounting a page type.
+/// Implemented by proc_macro_derive wicket_page.
+pub trait StaticMarkupIdentifier {
+    fn markup_type() -> &'static MarkupType;
+}
+
 pub trait FromPageParameters {
     fn from_page_params(page_params: Option<PageParameters>) -> Box<dyn WebPage>;
 }
 
-// TODO: Implement Send + Sync to allow multi-threaded
-// request handling and disk state persistence.
-pub trait WebPage: MarkupContainer + DynClone {
+/// A page is stored in the session and handled by any thread of the runtime, see
+/// [Component].
+pub trait WebPage: MarkupContainer + DynClone + Send + Sync {
     fn init(&self) {}
 }
 clone_trait_object!(WebPage);
@@ -188,51 +237,74 @@ impl Display for InternalId {
     }
 }
 
+/// A page read from the session page store, copied on the first change. A dirty handle
+/// is stored back as a new page version at the end of the request, see
+/// [RequestCycle::set_page_handle].
 pub enum PageHandle<'a> {
     Borrowed {
-        page: &'a dyn WebPage,
+        page: &'a (dyn WebPage + 'static),
         dirty: bool,
     },
     Owned {
-        page: Box<dyn WebPage + 'a>,
+        page: Box<dyn WebPage>,
         dirty: bool,
     },
 }
 
-impl<'a> std::ops::Deref for PageHandle<'a> {
-    type Target = dyn WebPage + 'a;
+impl std::ops::Deref for PageHandle<'_> {
+    type Target = dyn WebPage;
 
     fn deref(&self) -> &Self::Target {
-        match self {
-            PageHandle::Borrowed { page, dirty: _ } => *page,
-            PageHandle::Owned { page, dirty: _ } => page.as_ref(),
-        }
+        self.as_trait()
     }
 }
 
-impl<'a> PageHandle<'a> {
-    pub fn as_trait(&self) -> &dyn WebPage {
+impl PageHandle<'_> {
+    /// The page behind the handle.
+    pub fn as_trait(&self) -> &(dyn WebPage + 'static) {
         match self {
             PageHandle::Borrowed { page, dirty: _ } => *page,
             PageHandle::Owned { page, dirty: _ } => page.as_ref(),
         }
     }
 
-    pub fn to_mut(&mut self) -> &mut (dyn WebPage + 'a) {
+    /// The page to change, marks the handle dirty.
+    pub fn to_mut(&mut self) -> &mut (dyn WebPage + 'static) {
         if let PageHandle::Borrowed { page, dirty: _ } = *self {
             *self = PageHandle::Owned {
                 page: dyn_clone::clone_box(page),
-                dirty: false,
+                dirty: true,
             };
         }
 
         match self {
-            PageHandle::Owned { page, dirty: _ } => page.as_mut(),
+            PageHandle::Owned { page, dirty } => {
+                *dirty = true;
+                page.as_mut()
+            }
             _ => unreachable!(),
         }
     }
 
-    pub fn into_owned(self) -> Box<dyn WebPage + 'a> {
+    /// True when the page was changed and must be stored.
+    pub fn is_dirty(&self) -> bool {
+        match self {
+            PageHandle::Borrowed { dirty, .. } | PageHandle::Owned { dirty, .. } => *dirty,
+        }
+    }
+
+    /// Release the borrow of the session eg to pass the cycle to a listener, the page
+    /// remains dirty.
+    pub fn detach(self) -> PageHandle<'static> {
+        let dirty = self.is_dirty();
+        PageHandle::Owned {
+            page: self.into_owned(),
+            dirty,
+        }
+    }
+
+    /// Take the page out of the handle, a borrowed page is cloned.
+    pub fn into_owned(self) -> Box<dyn WebPage> {
         match self {
             PageHandle::Borrowed { page, dirty: _ } => dyn_clone::clone_box(page),
             PageHandle::Owned { page, dirty: _ } => page,
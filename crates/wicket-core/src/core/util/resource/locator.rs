@@ -4,8 +4,8 @@ use std::path::{Path, PathBuf};
 
 use crate::markup::{FileResourceStream, ResourceStream};
 
-/// Abstraction for finding and loading markup resources.
-pub trait ResourceStreamLocator {
+/// Abstraction for finding and loading markup and static resources.
+pub trait ResourceStreamLocator: Send + Sync {
     /// Attempt to locate a resource at the given path.
     /// Returns `Ok(File)` if found, `Err` if not found.
     fn locate(
@@ -74,7 +74,7 @@ impl ResourceStreamLocator for FileResourceStreamLocator {
         for root in &self.roots {
             let full_path = root.to_path_buf().join(&resource_path);
 
-            if full_path.exists() {
+            if full_path.is_file() {
                 let file = File::open(full_path)?;
                 let file_resource_stream = Box::from(FileResourceStream {
                     file,
@@ -8,3 +8,9 @@ pub mod protocol;
 pub mod request;
 pub mod session;
 pub mod settings;
+#[cfg(test)]
+mod test_util;
+
+/// Implement the async methods of [request::RequestHandler] and
+/// [components::MarkupContainer] with `#[async_trait]`.
+pub use async_trait::async_trait;
@@ -8,7 +8,9 @@ use std::borrow::Cow;
 use std::fs::File;
 use std::io;
 use std::io::Read;
+use std::io::Seek;
 use std::sync::OnceLock;
+use std::time::SystemTime;
 
 use once_cell::sync::Lazy;
 
@@ -85,7 +87,7 @@ impl Markup {
 
     /// Render from here.
     /// Move away from distributed render MarkupStream, MarkupContainer, MarkupResponse
-    pub fn render<T: MarkupContainer>(
+    pub fn render<T: MarkupContainer + ?Sized>(
         &self,
         response: &mut Response,
         markup_container: &T,
@@ -178,11 +180,31 @@ pub struct MarkupResource {
     pub markup: Markup,
 }
 
-pub trait ResourceStream {
+/// A reader able to serve byte ranges of a resource.
+pub trait SeekRead: Read + Seek + Send {}
+impl<T: Read + Seek + Send> SeekRead for T {}
+
+/// The content of a resource, read in chunks by the ResourceStreamRequestHandler.
+pub trait ResourceStream: Send {
     fn get_variation(&self) -> Option<&str> {
         None
     }
     fn get_read(&mut self) -> &mut dyn Read;
+
+    /// The size of the resource in bytes, when known.
+    fn length(&self) -> Option<u64> {
+        None
+    }
+
+    /// The last modification time of the resource, when known.
+    fn last_modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// Give up the underlying data as an owned reader for streaming to the client.
+    fn into_seekable(self: Box<Self>) -> Option<Box<dyn SeekRead>> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -212,4 +234,16 @@ impl ResourceStream for FileResourceStream {
     fn get_read(&mut self) -> &mut dyn Read {
         &mut self.file as &mut dyn Read
     }
+
+    fn length(&self) -> Option<u64> {
+        self.file.metadata().ok().map(|m| m.len())
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.file.metadata().and_then(|m| m.modified()).ok()
+    }
+
+    fn into_seekable(self: Box<Self>) -> Option<Box<dyn SeekRead>> {
+        Some(Box::new(self.file))
+    }
 }
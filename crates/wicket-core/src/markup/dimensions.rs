@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use wicket_macro::load_html_dimensions;
 use wicket_macro_support::get_string_index;
 
@@ -9,21 +11,120 @@ pub struct ValidHtmlDimensions {
     pub country: Option<Vec<String>>,
 }
 
+/// A language and optional country eg fr_CA, the codes are ISO 639-1 lowercase and
+/// ISO 3166-1 uppercase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub lang: String,
+    pub country: Option<String>,
+}
+
+/// The indices into ValidHtmlDimensions selecting the markup of a render.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MarkupDimensions {
+    pub style: Option<u8>,
+    pub variation: Option<u8>,
+    pub lang: Option<u8>,
+    pub country: Option<u8>,
+}
+
+impl Locale {
+    /// The locale with the language lower cased and the country upper cased.
+    pub fn new(lang: &str, country: Option<&str>) -> Self {
+        Self {
+            lang: lang.to_ascii_lowercase(),
+            country: country.map(str::to_ascii_uppercase),
+        }
+    }
+
+    /// Parse a language tag eg "fr", "fr-CA" or "fr_CA".
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.trim().split(['-', '_']);
+        let lang = subtags.next().filter(|l| !l.is_empty() && *l != "*")?;
+        Some(Self::new(lang, subtags.next()))
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.lang)?;
+        if let Some(country) = &self.country {
+            write!(f, "_{}", country)?;
+        }
+        Ok(())
+    }
+}
+
 impl ValidHtmlDimensions {
+    /// The markup index of the configured style, None when not configured.
     pub fn get_style_index<S: AsRef<str>>(&self, style: S) -> Option<u8> {
-        get_string_index(style, get_valid_html_dimensions().style.as_deref())
+        get_string_index(style, self.style.as_deref())
     }
 
+    /// The markup index of the configured variation, None when not configured.
     pub fn get_variation_index<V: AsRef<str>>(&self, variation: V) -> Option<u8> {
-        get_string_index(variation, get_valid_html_dimensions().variation.as_deref())
+        get_string_index(variation, self.variation.as_deref())
     }
 
+    /// The markup index of the configured lang, None when not configured.
     pub fn get_lang_index<L: AsRef<str>>(&self, lang: L) -> Option<u8> {
-        get_string_index(lang, get_valid_html_dimensions().lang.as_deref())
+        get_string_index(lang, self.lang.as_deref())
     }
 
+    /// The markup index of the configured country, None when not configured.
     pub fn get_country_index<C: AsRef<str>>(&self, country: C) -> Option<u8> {
-        get_string_index(country, get_valid_html_dimensions().country.as_deref())
+        get_string_index(country, self.country.as_deref())
+    }
+
+    /// The markup indices of the locale, style and variation. Values not configured in
+    /// html_dimensions.toml have no index.
+    pub fn resolve(
+        &self,
+        locale: Option<&Locale>,
+        style: Option<&str>,
+        variation: Option<&str>,
+    ) -> MarkupDimensions {
+        let lang = locale.and_then(|l| self.get_lang_index(&l.lang));
+        MarkupDimensions {
+            style: style.and_then(|s| self.get_style_index(s)),
+            variation: variation.and_then(|v| self.get_variation_index(v)),
+            lang,
+            // A country without its language does not select markup.
+            country: lang
+                .and(locale.and_then(|l| l.country.as_ref()))
+                .and_then(|c| self.get_country_index(c)),
+        }
+    }
+
+    /// The preferred locale of an Accept-Language header eg "fr-CA,fr;q=0.9,en;q=0.8"
+    /// supported by the configured languages. The country is dropped when it is not
+    /// configured.
+    pub fn negotiate_locale(&self, accept_language: &str) -> Option<Locale> {
+        let mut ranges: Vec<(Locale, f32)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = Locale::parse(parts.next()?)?;
+                let quality = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+        // Stable, equal qualities keep the header order.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges.into_iter().find_map(|(locale, _)| {
+            self.get_lang_index(&locale.lang)?;
+            let country = locale
+                .country
+                .filter(|c| self.get_country_index(c).is_some());
+            Some(Locale {
+                lang: locale.lang,
+                country,
+            })
+        })
     }
 }
 
@@ -39,6 +140,7 @@ pub mod test {
     use wicket_macro::wicket_page;
     use wicket_request::request::mapper::parameter::PageParameters;
 
+    use super::{Locale, MarkupDimensions, ValidHtmlDimensions};
     use crate::components::{FromPageParameters, MarkupContainer, WebPage};
     #[test]
     pub fn test_dimension_html_load() {
@@ -72,7 +174,49 @@ pub mod test {
             html.trim_end()
         );
         assert!(_MARKUP_RESOURCE_VEC_DIMENSIONSTESTPAGE.len() == 1);
-        let page = page.lookup_markup(Some(1), None, None, None);
-        assert!(page.is_none());
+        // Without a file for the style the default is used.
+        let markup = page.lookup_markup(Some(1), None, Some(0), Some(0));
+        assert!(std::ptr::eq(markup_resource, markup.unwrap()));
+    }
+
+    #[test]
+    pub fn negotiate_locale_test() {
+        let dimensions = ValidHtmlDimensions {
+            style: Some(vec!["dark".to_string()]),
+            variation: None,
+            lang: Some(vec!["fr".to_string(), "en".to_string()]),
+            country: Some(vec!["CA".to_string(), "AU".to_string()]),
+        };
+        let negotiate = |header| dimensions.negotiate_locale(header).map(|l| l.to_string());
+
+        assert_eq!(Some("fr_CA".to_string()), negotiate("fr-CA,fr;q=0.9"));
+        assert_eq!(
+            Some("en_AU".to_string()),
+            negotiate("de;q=0.9, EN-au;q=0.5")
+        );
+        // Quality beats header order, q=0 is refused.
+        assert_eq!(
+            Some("en".to_string()),
+            negotiate("fr;q=0.2,en-US;q=0.8,*;q=0.9")
+        );
+        assert_eq!(None, negotiate("fr;q=0,de"));
+        assert_eq!(None, negotiate(""));
+
+        let locale = Locale::parse("fr_CA").unwrap();
+        assert_eq!(
+            MarkupDimensions {
+                style: Some(0),
+                variation: None,
+                lang: Some(0),
+                country: Some(0),
+            },
+            dimensions.resolve(Some(&locale), Some("dark"), Some("mobile"))
+        );
+        // A country without a supported language is ignored.
+        let locale = Locale::parse("de-CA").unwrap();
+        assert_eq!(
+            MarkupDimensions::default(),
+            dimensions.resolve(Some(&locale), Some("light"), None)
+        );
     }
 }
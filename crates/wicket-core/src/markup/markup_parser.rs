@@ -258,6 +258,19 @@ impl MarkupParser {
             };
         }
 
+        // The text following the last wicket tag.
+        let source_len = self.xml_parser.source().len();
+        let text_range = self.xml_parser.get_range_from_position_marker(source_len);
+        if !text_range.is_empty() {
+            match markup.last_mut() {
+                Some(MarkupElement::RawMarkup(last_raw))
+                    if last_raw.text_range.end == text_range.start =>
+                {
+                    last_raw.text_range.end = text_range.end;
+                }
+                _ => markup.push(MarkupElement::RawMarkup(RawMarkup { text_range })),
+            }
+        }
         Ok(markup)
     }
 
@@ -423,6 +436,28 @@ mod test {
         );
     }
 
+    #[test]
+    pub fn trailing_raw_markup_test() {
+        let markup_str =
+            "This is a test <a wicket:id=9> <b>bold</b></a> of the emergency broadcasting system";
+        let markup = MarkupParser::new(markup_str.to_owned())
+            .parse_markup()
+            .unwrap();
+        let text = markup.last().unwrap();
+        assert!(
+            matches!(text, MarkupElement::RawMarkup(rm) if &markup_str[rm.text_range.clone()] == " of the emergency broadcasting system")
+        );
+
+        // Markup without a wicket tag is a single raw text.
+        let markup_str = "<html><body>No components</body></html>";
+        let markup = MarkupParser::new(markup_str.to_owned())
+            .parse_markup()
+            .unwrap();
+        assert!(
+            matches!(&markup[..], [MarkupElement::RawMarkup(rm)] if &markup_str[rm.text_range.clone()] == markup_str)
+        );
+    }
+
     #[test]
     pub fn wicket_tag() {
         assert!(MarkupParser::new("<span wicket:id=\"test\"/>".to_owned())
@@ -1,46 +1,93 @@
 use std::collections::HashMap;
+use std::io;
 
 use crate::components::WebPage;
+use crate::session::SessionId;
+use crate::settings::{PageStoreSettings, PageStoreType};
+use disk::DiskPageStore;
 
-/// Per session page store.
-pub trait PageStore {
-    /// Return the version of the page.
-    /// Each time a page is created its instance is stored.
-    /// Each time a request mutates a page instance its version is stored.
-    /// When the number of versions of a page exceeds the version number, truncate
-    /// the store, so state changes to an old version reset the page history to the current
-    /// version.
-    fn store_page(&mut self, version: u16, page: Box<dyn WebPage>) -> u16;
-    fn get_page(&mut self, page_id: u16, version: u16) -> Option<&dyn WebPage>;
+pub mod disk;
+
+/// Per session page store of the page instances and their versions.
+pub trait PageStore: Send {
+    /// Store the page as a new version of the page instance, return the version.
+    /// The first page of an instance is version 0, each time a request mutates a page
+    /// instance the change is stored as the next version of the version it was derived
+    /// from. Newer versions are truncated, so state changes to an old version eg after the
+    /// back button reset the page history to the current version.
+    fn store_page(&mut self, page_id: u16, version: u16, page: Box<dyn WebPage>)
+        -> io::Result<u16>;
+
+    fn get_page(
+        &mut self,
+        page_id: u16,
+        version: u16,
+    ) -> io::Result<Option<&(dyn WebPage + 'static)>>;
+
+    /// The most recent version of the page instance.
+    fn get_latest_version(&self, page_id: u16) -> Option<u16>;
+}
+
+/// The version of a page derived from the version, given the most recent version.
+fn next_version(version: u16, latest: Option<u16>) -> u16 {
+    latest.map_or(0, |latest| version.min(latest) + 1)
+}
+
+/// Converts pages to and from bytes for the [disk::DiskPageStore], eg with serde
+/// prefixed by the page type id for [crate::session::page_factory::get_page_entry].
+pub trait PageSerializer: Send + Sync {
+    fn serialize(&self, page: &dyn WebPage) -> io::Result<Vec<u8>>;
+    fn deserialize(&self, data: &[u8]) -> io::Result<Box<dyn WebPage>>;
 }
 
+/// Keeps every version of every page in memory.
+#[derive(Default)]
 pub struct SimplePageStore {
     pages: HashMap<u16, Vec<Box<dyn WebPage>>>,
 }
 
 impl PageStore for SimplePageStore {
-    fn store_page(&mut self, version: u16, page: Box<dyn WebPage>) -> u16 {
-        let page_id = page.get_markup_identity().id;
-
+    fn store_page(
+        &mut self,
+        page_id: u16,
+        version: u16,
+        page: Box<dyn WebPage>,
+    ) -> io::Result<u16> {
+        let new_version = next_version(version, self.get_latest_version(page_id));
         let versions = self
             .pages
             .entry(page_id)
             .or_insert_with(|| Vec::with_capacity(1));
+        versions.truncate(new_version as usize);
+        versions.push(page);
+        Ok(new_version)
+    }
 
-        let no_versions = versions.len() as u16;
-        if version > (no_versions) {
-            versions.truncate(no_versions as usize);
-        }
+    fn get_page(
+        &mut self,
+        page_id: u16,
+        version: u16,
+    ) -> io::Result<Option<&(dyn WebPage + 'static)>> {
+        Ok(self
+            .pages
+            .get(&page_id)
+            .and_then(|versions| versions.get(version as usize))
+            .map(|boxed| boxed.as_ref()))
+    }
 
-        let new_version_index = versions.len() as u16;
-        versions.push(page);
-        new_version_index
+    fn get_latest_version(&self, page_id: u16) -> Option<u16> {
+        let versions = self.pages.get(&page_id)?;
+        versions.len().checked_sub(1).map(|latest| latest as u16)
     }
+}
 
-    fn get_page(&mut self, page_id: u16, version: u16) -> Option<&dyn WebPage> {
-        self.pages
-            .get(&page_id)?
-            .get(version as usize)
-            .map(|boxed| boxed.as_ref())
+/// The page store of a new session as configured by the settings.
+pub fn new_page_store(session_id: SessionId, settings: &PageStoreSettings) -> Box<dyn PageStore> {
+    match &settings.store_type {
+        PageStoreType::Memory => Box::new(SimplePageStore::default()),
+        PageStoreType::Disk(serializer) => {
+            Box::new(DiskPageStore::new(settings, serializer.clone()))
+        }
+        PageStoreType::Custom(factory) => factory(session_id),
     }
 }
@@ -0,0 +1,328 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::task::block_in_place;
+
+use crate::components::WebPage;
+use crate::page_store::{next_version, PageSerializer, PageStore};
+use crate::settings::PageStoreSettings;
+
+// The page id and version.
+type PageKey = (u16, u16);
+
+/// A per session page store keeping the most recently used page versions in memory and
+/// serializing the older versions to files, one per version, in a directory of the session.
+///
+/// The files of a session are limited to [PageStoreSettings::max_size_per_session], the
+/// oldest versions are deleted first and are no longer available eg to the back button of
+/// a long lived tab. The directory is removed when the store is dropped with its session.
+///
+/// The directory is named by a random id rather than the session id, it is kept when the
+/// session id is replaced eg on login and the cookie value is not written to disk. The
+/// file I/O runs while the session is locked, on a multi thread runtime in
+/// [block_in_place] so the other tasks of the worker are moved to another worker.
+pub struct DiskPageStore {
+    dir: PathBuf,
+    max_pages_in_memory: usize,
+    max_size: u64,
+    serializer: Arc<dyn PageSerializer>,
+    // Least recently used first.
+    memory: VecDeque<(PageKey, Box<dyn WebPage>)>,
+    // Oldest first with the file size.
+    disk: VecDeque<(PageKey, u64)>,
+    disk_size: u64,
+    // The most recent version of each page.
+    latest_versions: HashMap<u16, u16>,
+}
+
+impl DiskPageStore {
+    /// Create the store of the session pages under the settings directory.
+    pub fn new(settings: &PageStoreSettings, serializer: Arc<dyn PageSerializer>) -> Self {
+        Self {
+            dir: settings
+                .dir
+                .join(format!("{:032x}", rand::random::<u128>())),
+            max_pages_in_memory: settings.max_pages_in_memory.max(1),
+            max_size: settings.max_size_per_session,
+            serializer,
+            memory: VecDeque::new(),
+            disk: VecDeque::new(),
+            disk_size: 0,
+            latest_versions: HashMap::new(),
+        }
+    }
+
+    /// The bytes of the session page files.
+    pub fn get_disk_size(&self) -> u64 {
+        self.disk_size
+    }
+
+    fn page_path(&self, (page_id, version): PageKey) -> PathBuf {
+        self.dir.join(format!("{}-{}.page", page_id, version))
+    }
+
+    /// Move the least recently used pages to disk leaving room for one more in memory.
+    fn make_room(&mut self) -> io::Result<()> {
+        while self.memory.len() >= self.max_pages_in_memory {
+            let Some((key, page)) = self.memory.pop_front() else {
+                break;
+            };
+            self.write_page(key, page.as_ref())?;
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, key: PageKey, page: &dyn WebPage) -> io::Result<()> {
+        let data = self.serializer.serialize(page)?;
+        let size = data.len() as u64;
+        if size > self.max_size {
+            // The version can never fit the budget, it is dropped.
+            return Ok(());
+        }
+        let mut removed = Vec::new();
+        while self.disk_size + size > self.max_size {
+            let Some((oldest, oldest_size)) = self.disk.pop_front() else {
+                break;
+            };
+            self.disk_size -= oldest_size;
+            removed.push(self.page_path(oldest));
+        }
+        let path = self.page_path(key);
+        blocking(|| {
+            for oldest in removed {
+                fs::remove_file(oldest)?;
+            }
+            fs::create_dir_all(&self.dir)?;
+            fs::write(path, data)
+        })?;
+        self.disk.push_back((key, size));
+        self.disk_size += size;
+        Ok(())
+    }
+
+    /// Remove the versions of the page from the version on.
+    fn truncate(&mut self, page_id: u16, version: u16) -> io::Result<()> {
+        let removed = |(id, v): &PageKey| *id == page_id && *v >= version;
+        self.memory.retain(|(key, _)| !removed(key));
+        let mut paths = Vec::new();
+        let mut disk = std::mem::take(&mut self.disk);
+        disk.retain(|(key, size)| {
+            if !removed(key) {
+                return true;
+            }
+            self.disk_size -= size;
+            paths.push(self.page_path(*key));
+            false
+        });
+        self.disk = disk;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        blocking(|| paths.iter().try_for_each(fs::remove_file))
+    }
+}
+
+impl PageStore for DiskPageStore {
+    fn store_page(
+        &mut self,
+        page_id: u16,
+        version: u16,
+        page: Box<dyn WebPage>,
+    ) -> io::Result<u16> {
+        let new_version = next_version(version, self.get_latest_version(page_id));
+        self.truncate(page_id, new_version)?;
+        self.latest_versions.insert(page_id, new_version);
+
+        self.make_room()?;
+        self.memory.push_back(((page_id, new_version), page));
+        Ok(new_version)
+    }
+
+    fn get_page(
+        &mut self,
+        page_id: u16,
+        version: u16,
+    ) -> io::Result<Option<&(dyn WebPage + 'static)>> {
+        let key = (page_id, version);
+        if let Some(index) = self.memory.iter().position(|(k, _)| *k == key) {
+            let entry = self.memory.remove(index).expect("Index is in range");
+            self.memory.push_back(entry);
+        } else if let Some(index) = self.disk.iter().position(|(k, _)| *k == key) {
+            let (_, size) = self.disk.remove(index).expect("Index is in range");
+            self.disk_size -= size;
+            let path = self.page_path(key);
+            let data = blocking(|| {
+                let data = fs::read(&path)?;
+                fs::remove_file(&path)?;
+                Ok::<_, io::Error>(data)
+            })?;
+            let page = self.serializer.deserialize(&data)?;
+            self.make_room()?;
+            self.memory.push_back((key, page));
+        } else {
+            return Ok(None);
+        }
+        Ok(self.memory.back().map(|(_, page)| page.as_ref()))
+    }
+
+    fn get_latest_version(&self, page_id: u16) -> Option<u16> {
+        self.latest_versions.get(&page_id).copied()
+    }
+}
+
+impl Drop for DiskPageStore {
+    fn drop(&mut self) {
+        // Nothing was written when the directory does not exist.
+        let _ = blocking(|| fs::remove_dir_all(&self.dir));
+    }
+}
+
+/// Run the file I/O in [block_in_place] on a multi thread runtime, a current thread
+/// runtime is blocked as [block_in_place] panics on it.
+fn blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => block_in_place(f),
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::sync::Arc;
+
+    use crate::components::{ComponentId, WebPage};
+    use crate::page_store::{new_page_store, PageSerializer, PageStore};
+    use crate::request::{Response, ResponseBody};
+    use crate::session::SessionRegistry;
+    use crate::settings::{PageStoreSettings, PageStoreType};
+    use crate::test_util::ProductPage;
+
+    use super::DiskPageStore;
+
+    const PAGE_SIZE: usize = 100;
+
+    // The count padded to PAGE_SIZE bytes.
+    struct CounterSerializer {}
+    impl PageSerializer for CounterSerializer {
+        fn serialize(&self, page: &dyn WebPage) -> io::Result<Vec<u8>> {
+            let mut data = vec![0; PAGE_SIZE];
+            data[..4].copy_from_slice(&count(page).parse::<u32>().unwrap().to_le_bytes());
+            Ok(data)
+        }
+
+        fn deserialize(&self, data: &[u8]) -> io::Result<Box<dyn WebPage>> {
+            let count = u32::from_le_bytes(data[..4].try_into().unwrap());
+            Ok(Box::new(ProductPage { count }))
+        }
+    }
+
+    fn count(page: &dyn WebPage) -> String {
+        let mut response = Response::new();
+        response.set_body(ResponseBody::Buffered(vec![]));
+        page.render_component(ComponentId::TagId(0), &mut response)
+            .unwrap();
+        match response.get_body() {
+            ResponseBody::Buffered(body) => String::from_utf8(body.clone()).unwrap(),
+            _ => panic!("Response is buffered"),
+        }
+    }
+
+    #[test]
+    pub fn disk_page_store_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let settings = PageStoreSettings {
+            max_pages_in_memory: 2,
+            max_size_per_session: (PAGE_SIZE * 5 / 2) as u64,
+            dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let mut store = DiskPageStore::new(&settings, Arc::new(CounterSerializer {}));
+        let dir = store.dir.clone();
+        let page_id = 3;
+
+        let mut version = 0;
+        for count in 0..5 {
+            version = store
+                .store_page(page_id, version, Box::new(ProductPage { count }))
+                .unwrap();
+            assert_eq!(count as u16, version);
+        }
+        // Versions 3 and 4 are in memory, 1 and 2 on disk and 0 exceeded the budget.
+        assert_eq!(2, store.memory.len());
+        assert_eq!(2 * PAGE_SIZE as u64, store.get_disk_size());
+        assert_eq!(2, std::fs::read_dir(&dir).unwrap().count());
+        assert!(store.get_page(page_id, 0).unwrap().is_none());
+
+        let page = store.get_page(page_id, 1).unwrap().unwrap();
+        assert_eq!("1", count(page));
+        let page = store.get_page(page_id, 4).unwrap().unwrap();
+        assert_eq!("4", count(page));
+        // Reading version 1 moved version 3 to disk.
+        assert_eq!(
+            vec![(page_id, 2), (page_id, 3)],
+            store.disk.iter().map(|(key, _)| *key).collect::<Vec<_>>()
+        );
+
+        // A change to an old version discards the newer versions.
+        let version = store
+            .store_page(page_id, 1, Box::new(ProductPage { count: 10 }))
+            .unwrap();
+        assert_eq!(2, version);
+        assert_eq!("10", count(store.get_page(page_id, 2).unwrap().unwrap()));
+        assert!(store.get_page(page_id, 3).unwrap().is_none());
+        assert!(store.get_page(page_id, 4).unwrap().is_none());
+        assert_eq!(0, store.get_disk_size());
+        assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+
+        drop(store);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    pub async fn disk_page_store_session_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let settings = PageStoreSettings {
+            store_type: PageStoreType::Disk(Arc::new(CounterSerializer {})),
+            max_pages_in_memory: 1,
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        let registry = SessionRegistry::default();
+        let session_id = registry.new_session_with(|id| new_page_store(id, &settings));
+
+        // The file I/O of a worker thread runs in block_in_place.
+        let page_id = {
+            let handle = registry.get_session_handle(session_id).unwrap();
+            let mut session = handle.lock().await;
+            let page_id = session
+                .add_page(Box::new(ProductPage { count: 1 }))
+                .unwrap();
+            session
+                .add_page(Box::new(ProductPage { count: 2 }))
+                .unwrap();
+            page_id
+        };
+        let store_dirs = || {
+            std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let store_dir = store_dirs();
+        assert_eq!(1, store_dir.len());
+        assert_ne!(session_id.to_string(), store_dir[0]);
+
+        // The store directory is kept by the session under its new id.
+        let new_id = registry.replace_session_id(session_id).unwrap();
+        assert_eq!(store_dir, store_dirs());
+        let handle = registry.get_session_handle(new_id).unwrap();
+        let mut session = handle.lock().await;
+        assert_eq!("1", count(session.get_page(page_id, 0).unwrap().unwrap()));
+    }
+}
@@ -1,16 +1,54 @@
 use std::io::Error;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{PoisonError, RwLock};
 
+use tokio::task::JoinHandle;
+use wicket_request::request::post::multipart::MultipartSettings;
+
+use crate::components::StaticMarkupIdentifier;
+use crate::core::util::resource::locator::FileResourceStreamLocator;
+use crate::markup::dimensions::{get_valid_html_dimensions, ValidHtmlDimensions};
+use crate::request::cycle::listener::RequestCycleListener;
 use crate::request::cycle::RequestCycle;
-use crate::request::cycle::SessionProvider;
-use crate::request::mapper::get_default_mappers;
+use crate::request::exception::{DefaultExceptionMapper, ExceptionMapper};
+use crate::request::mapper::crypto::CryptoMapper;
+use crate::request::mapper::{get_default_mappers, MountedMapper, PackageMapper, ResourceMapper};
 use crate::request::{Request, RequestMapper, Response};
 use crate::session::SessionRegistry;
+use crate::settings::{
+    ExceptionSettings, PageSettings, PageStoreSettings, RedirectSettings, RenderStrategy,
+    SessionCookieSettings, SessionSettings,
+};
 
 pub struct WebApplication {
+    /// The mappers resolving the request urls, see [WebApplication::mount].
     pub app_request_mappers: RwLock<Vec<RequestMapper>>,
+    /// The sessions of the application by id.
     pub sessions: Arc<SessionRegistry>,
+    /// The limits of the form bodies, see [RequestCycle::get_post_parameters]. The
+    /// protocol bridge answers 413 to a body larger than max_request_size.
+    pub multipart_settings: MultipartSettings,
+    /// The attributes of the session cookie.
+    pub session_cookie_settings: SessionCookieSettings,
+    /// The expiry of idle sessions, see [WebApplication::spawn_session_sweeper].
+    pub session_settings: SessionSettings,
+    /// The handling of expired page instances.
+    pub page_settings: PageSettings,
+    /// The hosts a component may redirect to.
+    pub redirect_settings: RedirectSettings,
+    /// How a page changed by a listener is rendered, redirect to buffer by default.
+    pub render_strategy: RenderStrategy,
+    /// The response to a failed request, see [ExceptionSettings].
+    pub exception_mapper: Box<dyn ExceptionMapper>,
+    /// The error pages of the DefaultExceptionMapper and the development mode.
+    pub exception_settings: ExceptionSettings,
+    /// The hooks called on each request, see [WebApplication::add_request_cycle_listener].
+    pub request_cycle_listeners: Vec<Box<dyn RequestCycleListener>>,
+    /// The page store of each new session, in memory by default.
+    pub page_store_settings: PageStoreSettings,
+    /// The styles, variations and locales of the markup files.
+    pub html_dimensions: &'static ValidHtmlDimensions,
 }
 
 impl Default for WebApplication {
@@ -18,6 +56,17 @@ impl Default for WebApplication {
         Self {
             app_request_mappers: RwLock::from(get_default_mappers()),
             sessions: Arc::from(SessionRegistry::default()),
+            multipart_settings: MultipartSettings::default(),
+            session_cookie_settings: SessionCookieSettings::default(),
+            session_settings: SessionSettings::default(),
+            page_settings: PageSettings::default(),
+            redirect_settings: RedirectSettings::default(),
+            render_strategy: RenderStrategy::default(),
+            exception_mapper: Box::new(DefaultExceptionMapper::default()),
+            exception_settings: ExceptionSettings::default(),
+            request_cycle_listeners: Vec::new(),
+            page_store_settings: PageStoreSettings::default(),
+            html_dimensions: get_valid_html_dimensions(),
         }
     }
 }
@@ -27,11 +76,50 @@ impl WebApplication {
         let mut map = self
             .app_request_mappers
             .write()
-            .expect("Error locking root_request_mapper?");
+            .unwrap_or_else(PoisonError::into_inner);
         let idx = if pos > map.len() { map.len() } else { pos };
         map.insert(idx, mapper);
     }
 
+    /// Mount a page type at a path template eg "/product/${id}/#{tab}".
+    /// `${}` segments are required and `#{}` segments optional page parameters.
+    pub fn mount_page<P: StaticMarkupIdentifier>(&mut self, path: &str) {
+        let mapper = MountedMapper::new(path, P::markup_type());
+        self.mount(usize::MAX, RequestMapper::Mounted(mapper));
+    }
+
+    /// Mount every page registered in the rust module at the url prefix eg
+    /// `mount_package("/admin", "myapp::admin")` serves "/admin/UserPage".
+    pub fn mount_package(&mut self, prefix: &str, module_path: &str) {
+        let mapper = PackageMapper::new(prefix, module_path);
+        self.mount(usize::MAX, RequestMapper::Package(mapper));
+    }
+
+    /// Serve the files below the root directory at the url prefix eg "/static".
+    /// Use [ResourceMapper::with_cache_control] and mount for a caching policy.
+    pub fn mount_resources(&mut self, prefix: &str, root: impl Into<PathBuf>) {
+        let locator = FileResourceStreamLocator::new(vec![root.into()]);
+        let mapper = ResourceMapper::new(prefix, Box::new(locator));
+        self.mount(usize::MAX, RequestMapper::Resource(mapper));
+    }
+
+    /// Add a listener called on each request after the listeners added before it.
+    pub fn add_request_cycle_listener(&mut self, listener: impl RequestCycleListener + 'static) {
+        self.request_cycle_listeners.push(Box::new(listener));
+    }
+
+    /// Encrypt the urls of all the mappers mounted so far, see [CryptoMapper], their plain
+    /// page urls are refused. Mappers mounted afterwards produce plain urls.
+    pub fn encrypt_urls(&mut self, key: &[u8; 32]) {
+        let mut mappers = self
+            .app_request_mappers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let wrapped = std::mem::take(&mut *mappers);
+        let crypto_mapper = CryptoMapper::new(key, wrapped);
+        mappers.push(RequestMapper::Custom(Box::new(crypto_mapper)));
+    }
+
     /// Port of WicketFilter.processRequest()
     /// This is the entry point from the hyper bridge.
     pub async fn process_request(self: &Arc<Self>, request: Request) -> Result<Response, Error> {
@@ -40,19 +128,27 @@ impl WebApplication {
 
         // 2. Execute the lifecycle (The "Heavy Lifting")
         // This mirrors RequestCycle.process() in Java
-        cycle
-            .process_request(&mut SessionProvider::default())
-            .await?;
+        cycle.process_request().await?;
 
         // 3. Finalize and return
-        Ok(cycle.to_response())
+        Ok(cycle.take_response())
     }
 
-    pub fn create_request_cycle(self: &Arc<Self>, request: Request) -> RequestCycle<'_> {
+    /// The RequestCycle of the request served by this application.
+    pub fn create_request_cycle(self: &Arc<Self>, request: Request) -> RequestCycle {
         RequestCycle::new(self.clone(), request, Response::new())
     }
 
     pub fn get_session_registry(&self) -> Arc<SessionRegistry> {
         self.sessions.clone()
     }
+
+    /// Expire the idle sessions in the background, see [SessionSettings].
+    /// Must be called within a tokio runtime.
+    pub fn spawn_session_sweeper(&self) -> JoinHandle<()> {
+        self.sessions.spawn_sweeper(
+            self.session_settings.idle_timeout,
+            self.session_settings.sweep_interval,
+        )
+    }
 }
@@ -1,25 +1,34 @@
 pub mod cycle;
+pub mod exception;
 pub mod handler;
 pub mod mapper;
+pub mod range;
 
-use std::collections::HashMap;
 use std::io::Error;
 use std::io::Write;
 
+use async_trait::async_trait;
 use bytes::Bytes;
 use cookie::Cookie;
 use http::request::Parts;
-use url::Url;
+use wicket_request::request::mapper::info::{ComponentInfo, PageComponentInfo};
+use wicket_request::request::mapper::parameter::PageParameters;
+use wicket_request::request::post::multipart::{self, MultipartError, MultipartSettings};
+use wicket_request::request::post::PostParameters;
+use wicket_request::request::url::Url;
 
 use crate::components::WebPage;
 use crate::request::cycle::HandlerResult;
 use crate::request::cycle::RequestCycle;
-use crate::request::cycle::SessionProvider;
 use crate::request::handler::PageProvider;
-use crate::request::mapper::{BookmarkableMapper, MountedMapper, PackageMapper, ResourceMapper};
+use crate::request::mapper::{
+    BookmarkableMapper, MountedMapper, PackageMapper, PageInstanceMapper, ResourceMapper,
+};
+use crate::session::SessionId;
 
-static SESSION_ID_COOKIE_NAME: &str = "SESSION_ID";
+pub(crate) static SESSION_ID_COOKIE_NAME: &str = "SESSION_ID";
 
+#[derive(Clone)]
 pub enum RequestBody {
     None,
     Bytes(Bytes),
@@ -35,11 +44,70 @@ impl Request {
         Self { parts, body }
     }
 
-    pub fn get_session_id(&self) -> Option<u32> {
+    /// The request path and query relative to the application root.
+    pub fn get_url(&self) -> Url {
+        self.parts
+            .uri
+            .path_and_query()
+            .map(|pq| Url::parse(pq.as_str()))
+            .unwrap_or_default()
+    }
+
+    /// The query string parameters of the request, without the page instance info.
+    pub fn get_query_parameters(&self) -> PageParameters {
+        let mut url = self.get_url();
+        PageComponentInfo::take_from_url(&mut url);
+        PageParameters::from_url(&url)
+    }
+
+    /// The form parameters of an `application/x-www-form-urlencoded` or
+    /// `multipart/form-data` body, empty for any other body. Uploaded files are spilled
+    /// to the settings temp_dir and deleted when the parameters are dropped.
+    pub fn get_post_parameters(
+        &self,
+        settings: &MultipartSettings,
+    ) -> Result<PostParameters, MultipartError> {
+        let RequestBody::Bytes(body) = &self.body else {
+            return Ok(PostParameters::new());
+        };
+        let Some(content_type) = self
+            .parts
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return Ok(PostParameters::new());
+        };
+
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            if body.len() as u64 > settings.max_request_size {
+                return Err(MultipartError::RequestTooLarge {
+                    limit: settings.max_request_size,
+                });
+            }
+            Ok(PostParameters::parse_urlencoded(body))
+        } else if mime.eq_ignore_ascii_case("multipart/form-data") {
+            let boundary =
+                multipart::boundary(content_type).ok_or(MultipartError::MissingBoundary)?;
+            multipart::parse(body.as_ref(), &boundary, settings)
+        } else {
+            Ok(PostParameters::new())
+        }
+    }
+
+    /// A request of the wicket ajax script, marked by the Wicket-Ajax header.
+    pub fn is_ajax(&self) -> bool {
+        self.parts
+            .headers
+            .get("Wicket-Ajax")
+            .is_some_and(|value| value == "true")
+    }
+
+    /// The session id from the session cookie.
+    pub fn get_session_id(&self) -> Option<SessionId> {
         self.extract_cookie(SESSION_ID_COOKIE_NAME)
-            .map(|id| u32::from_str_radix(&id, 16))
-            .transpose()
-            .unwrap_or(None)
+            .and_then(|id| SessionId::parse(&id))
     }
 
     pub fn extract_cookie(&self, cookie_name: &str) -> Option<String> {
@@ -74,7 +142,11 @@ impl ResponseBody {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
         match self {
             ResponseBody::Buffered(buff) => buff.write(buf),
-            _ => unreachable!(),
+            ResponseBody::Streaming(_) => Err(Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Can not write to a streaming Response body!",
+            )),
+            ResponseBody::Empty => unreachable!(),
         }
     }
 }
@@ -83,7 +155,7 @@ impl ResponseBody {
 pub struct Response {
     body: ResponseBody,
     content_type: Option<String>,
-    headers: Option<HashMap<String, String>>,
+    headers: Option<Vec<(String, String)>>,
     /// Status code (e.g., 200)
     pub status: u16,
 }
@@ -106,13 +178,45 @@ impl Response {
         &self.body
     }
 
+    /// Move the body out of the response, leaving it empty.
+    pub fn take_body(&mut self) -> ResponseBody {
+        std::mem::take(&mut self.body)
+    }
+
+    /// The Content-Type header of the response.
+    pub fn get_content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The headers of the response in the order added, a name repeats for each value.
+    pub fn get_headers(&self) -> Option<&[(String, String)]> {
+        self.headers.as_deref()
+    }
+
+    /// The first value of the header, the name is case insensitive.
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.get_headers()?
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
     pub fn set_content_type(&mut self, content_type: impl Into<String>) {
         self.content_type = Some(content_type.into());
     }
 
+    /// Set the header, replacing the values of the same name.
     pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
-        let header_map = self.headers.get_or_insert(HashMap::with_capacity(2));
-        header_map.insert(name.into(), value.into());
+        let name = name.into();
+        let headers = self.headers.get_or_insert(Vec::with_capacity(2));
+        headers.retain(|(header, _)| !header.eq_ignore_ascii_case(&name));
+        headers.push((name, value.into()));
+    }
+
+    /// Add a value of the header, eg one Set-Cookie header per cookie.
+    pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let headers = self.headers.get_or_insert(Vec::with_capacity(2));
+        headers.push((name.into(), value.into()));
     }
 
     pub fn write_str(&mut self, buf: &str) -> std::result::Result<(), Error> {
@@ -138,6 +242,7 @@ pub enum RequestMapper {
     Package(PackageMapper),
     Resource(ResourceMapper),
     Bookmarkable(BookmarkableMapper),
+    PageInstance(PageInstanceMapper),
     Custom(Box<dyn RequestMapperLogic>),
 }
 
@@ -164,21 +269,96 @@ impl RequestMapperLogic for RequestMapper {
             RequestMapper::Package(rm) => rm.map_request(request),
             RequestMapper::Resource(rm) => rm.map_request(request),
             RequestMapper::Bookmarkable(rm) => rm.map_request(request),
+            RequestMapper::PageInstance(rm) => rm.map_request(request),
             RequestMapper::Custom(rm) => rm.map_request(request),
         }
     }
 
-    fn map_handler(&self, _handler: &dyn RequestHandler) -> Option<Url> {
-        todo!()
+    fn map_handler(&self, handler: &dyn RequestHandler) -> Option<Url> {
+        match self {
+            RequestMapper::Mounted(rm) => rm.map_handler(handler),
+            RequestMapper::Package(rm) => rm.map_handler(handler),
+            RequestMapper::Resource(rm) => rm.map_handler(handler),
+            RequestMapper::Bookmarkable(rm) => rm.map_handler(handler),
+            RequestMapper::PageInstance(rm) => rm.map_handler(handler),
+            RequestMapper::Custom(rm) => rm.map_handler(handler),
+        }
     }
 }
 
-pub trait RequestHandler {
-    fn respond(
-        &self,
-        cycle: &mut RequestCycle,
-        session_provider: &mut SessionProvider,
-    ) -> std::io::Result<HandlerResult>;
+/// Responds to a request. The respond future may await eg a database call of a
+/// component listener, it runs on the task of the request with the session locked and
+/// may move between the threads of the runtime at each await.
+#[async_trait]
+pub trait RequestHandler: Send + Sync {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult>;
     fn get_response_page(&self) -> &Option<Box<dyn WebPage>>;
     fn as_page_provider(&self) -> &Option<PageProvider>;
+    /// The component listener the handler invokes, encoded in page instance urls.
+    fn get_component_info(&self) -> Option<&ComponentInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use wicket_request::request::post::multipart::{MultipartError, MultipartSettings};
+
+    use crate::test_util::request;
+
+    use super::{Request, RequestBody};
+
+    fn post(content_type: &str, body: &'static [u8]) -> Request {
+        let (parts, _) = http::Request::builder()
+            .method("POST")
+            .uri("/form")
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(())
+            .unwrap()
+            .into_parts();
+        Request::new(parts, RequestBody::Bytes(Bytes::from_static(body)))
+    }
+
+    #[test]
+    pub fn get_post_parameters_test() {
+        let settings = MultipartSettings::default();
+        let params = post("application/x-www-form-urlencoded", b"a=1&b=x+y")
+            .get_post_parameters(&settings)
+            .unwrap();
+        assert_eq!(Some("x y"), params.get_parameter_value("b"));
+
+        let params = post(
+            "multipart/form-data; boundary=XyZ",
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--XyZ--\r\n",
+        )
+        .get_post_parameters(&settings)
+        .unwrap();
+        assert_eq!(Some("1"), params.get_parameter_value("a"));
+
+        assert!(post("application/json", b"{\"a\":1}")
+            .get_post_parameters(&settings)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            post("multipart/form-data", b"").get_post_parameters(&settings),
+            Err(MultipartError::MissingBoundary)
+        ));
+        let settings = MultipartSettings {
+            max_request_size: 4,
+            ..Default::default()
+        };
+        assert!(matches!(
+            post("application/x-www-form-urlencoded", b"a=1&b=2").get_post_parameters(&settings),
+            Err(MultipartError::RequestTooLarge { limit: 4 })
+        ));
+    }
+
+    #[test]
+    pub fn request_query_parameters_test() {
+        let params = request("/product?2-1&page=3&tag=a%20b&tag=c", None).get_query_parameters();
+        assert_eq!(Ok(3), params.get_i64("page"));
+        assert_eq!(vec!["a b", "c"], params.get_all("tag").collect::<Vec<_>>());
+        assert_eq!(3, params.named_parameters.len());
+    }
 }
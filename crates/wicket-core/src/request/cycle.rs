@@ -1,15 +1,37 @@
-use std::{io::Result, sync::Arc};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::{
+    io::Result,
+    sync::{Arc, PoisonError},
+};
 
-use tokio::sync::{Mutex, MutexGuard};
-use url::Url;
+use cookie::{time::Duration, Cookie};
+use futures_util::FutureExt;
+use tokio::sync::OwnedMutexGuard;
+use wicket_request::request::post::multipart::MultipartError;
+use wicket_request::request::post::PostParameters;
+use wicket_request::request::url::Url;
 
 use crate::{
-    components::WebPage,
+    components::{PageHandle, WebPage},
+    markup::dimensions::{Locale, MarkupDimensions},
+    page_store,
     protocol::http::WebApplication,
-    request::{Request, RequestHandler, RequestMapperLogic, RequestMappingResult, Response},
-    session::SessionData,
+    request::{
+        cycle::listener::RequestCycleListener,
+        exception::RequestFailure,
+        handler::{
+            ErrorCodeRequestHandler, PageExpiredError, PageProvider, RenderPageRequestHandler,
+        },
+        Request, RequestHandler, RequestMapperLogic, RequestMappingResult, Response,
+        SESSION_ID_COOKIE_NAME,
+    },
+    session::{SessionData, SessionId},
 };
 
+pub mod listener;
+
 pub enum RedirectAction {
     /// No navigation change.
     None,
@@ -26,49 +48,198 @@ pub enum HandlerResult {
     Schedule(Box<dyn RequestHandler>),
 }
 
-/// An anchor outside of the RequestCycle.
-#[derive(Default)]
-pub struct SessionProvider {
-    pub session_handle: Option<Arc<Mutex<SessionData>>>,
+/// The change to the session cookie of the client.
+#[derive(Clone, Copy)]
+enum SessionCookie {
+    /// A new or replaced session id.
+    Issue(SessionId),
+    /// The session is gone.
+    Expire,
 }
 
-pub struct RequestCycle<'a> {
+/// The state of a request while it is processed: the request, the response and the session.
+pub struct RequestCycle {
     pub request: Request,
     pub response: Response,
     pub app: Arc<WebApplication>,
-    session_guard: Option<MutexGuard<'a, SessionData>>,
+    // The session is locked for the whole request, pages are not shared between requests.
+    pub(crate) session_guard: Option<OwnedMutexGuard<SessionData>>,
+    // The id of the locked session.
+    session_id: Option<SessionId>,
+    session_cookie: Option<SessionCookie>,
+    post_parameters: Option<PostParameters>,
+    // The pages changed by the handler with the page id and version they were read from,
+    // stored as new versions once the handler completes.
+    dirty_pages: Vec<(u16, u16, Box<dyn WebPage>)>,
+    // Key: the type of the value, see [RequestCycle::set_meta_data].
+    meta_data: HashMap<TypeId, Box<dyn Any + Send>>,
 }
 
-impl<'a> RequestCycle<'a> {
+impl RequestCycle {
     pub fn new(app: Arc<WebApplication>, request: Request, response: Response) -> Self {
         Self {
             app,
             request,
             response,
             session_guard: None,
+            session_id: None,
+            session_cookie: None,
+            post_parameters: None,
+            dirty_pages: Vec::new(),
+            meta_data: HashMap::new(),
         }
     }
 
-    pub(crate) async fn process_request(
-        &mut self,
-        session_provider: &mut SessionProvider,
-    ) -> Result<()> {
-        let mut handler = self
-            .resolve_request_handler(&self.request)
-            .expect("Error: no handler found!")
-            .handler;
+    pub(crate) async fn process_request(&mut self) -> Result<()> {
+        // Page instance urls are resolved against the session pages.
+        self.get_session_mut().await;
+        self.notify_listeners(|listener, cycle| listener.on_begin_request(cycle));
+        let result = self.process_handlers().await;
+        self.notify_listeners(|listener, cycle| listener.on_end_request(cycle));
+        self.notify_listeners(|listener, cycle| listener.on_detach(cycle));
+        result
+    }
 
+    async fn process_handlers(&mut self) -> Result<()> {
+        // The render buffered by RedirectToBuffer is served without resolving a handler.
+        if let Some(buffered) = self.take_buffered_response() {
+            self.response = buffered;
+            self.set_session_cookie();
+            return Ok(());
+        }
+
+        let handler = match self.resolve_request_handler(&self.request) {
+            Some(result) => {
+                let handler = result.handler;
+                self.notify_listeners(|listener, cycle| {
+                    listener.on_request_handler_resolved(cycle, handler.as_ref())
+                });
+                handler
+            }
+            None => self.map_failure(RequestFailure::NoHandler),
+        };
+        if let Err(failure) = self.respond(handler).await {
+            let handler = self.map_failure(failure);
+            // A failing error page is left to the protocol bridge.
+            self.respond(handler).await?;
+        }
+        self.set_session_cookie();
+        Ok(())
+    }
+
+    /// Run the handler and the handlers it schedules. A panic of a handler is caught
+    /// and returned as a failure, the session stays locked by the cycle.
+    async fn respond(
+        &mut self,
+        mut handler: Box<dyn RequestHandler>,
+    ) -> std::result::Result<(), RequestFailure> {
         loop {
-            match handler.respond(self, session_provider)? {
-                HandlerResult::Complete => break,
+            let result = AssertUnwindSafe(async {
+                let result = handler.respond(self).await?;
+                self.notify_listeners(|listener, cycle| {
+                    listener.on_request_handler_executed(cycle, handler.as_ref())
+                });
+                Ok(result)
+            })
+            .catch_unwind()
+            .await
+            .map_err(RequestFailure::from_panic)?;
+            let result = match result {
+                Ok(result) => result,
+                Err(e) if PageExpiredError::find(&e).is_some() => {
+                    HandlerResult::Schedule(self.page_expired_handler())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.store_dirty_pages()?;
+            match result {
+                HandlerResult::Complete => return Ok(()),
                 HandlerResult::Schedule(next_handler) => handler = next_handler,
             };
         }
-        Ok(())
     }
 
-    pub(crate) fn to_response(&self) -> Response {
-        todo!()
+    /// The handler of the failure from the first listener providing one, else from the
+    /// application ExceptionMapper. The partial response and the pages changed by the
+    /// failed handler are discarded.
+    fn map_failure(&mut self, failure: RequestFailure) -> Box<dyn RequestHandler> {
+        self.response = Response::new();
+        self.dirty_pages.clear();
+        let app = self.app.clone();
+        let mut handler = None;
+        for listener in &app.request_cycle_listeners {
+            let listener_handler = listener.on_exception(self, &failure);
+            handler = handler.or(listener_handler);
+        }
+        handler.unwrap_or_else(|| app.exception_mapper.map(&failure, self))
+    }
+
+    fn notify_listeners(&mut self, hook: impl Fn(&dyn RequestCycleListener, &mut RequestCycle)) {
+        let app = self.app.clone();
+        for listener in &app.request_cycle_listeners {
+            hook(listener.as_ref(), self);
+        }
+    }
+
+    /// Store the value of the type for the rest of the request eg the transaction of a
+    /// [RequestCycleListener], return the previous value.
+    pub fn set_meta_data<T: Any + Send>(&mut self, value: T) -> Option<T> {
+        self.meta_data
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// The value of the type stored by [RequestCycle::set_meta_data].
+    pub fn get_meta_data<T: Any + Send>(&self) -> Option<&T> {
+        self.meta_data
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// The value of the type to change in place.
+    pub fn get_meta_data_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
+        self.meta_data
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Take the value of the type out of the request.
+    pub fn remove_meta_data<T: Any + Send>(&mut self) -> Option<T> {
+        self.meta_data
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// The response rendered for the redirect to this GET, see
+    /// [crate::settings::RenderStrategy::RedirectToBuffer].
+    fn take_buffered_response(&mut self) -> Option<Response> {
+        if self.request.parts.method != http::Method::GET {
+            return None;
+        }
+        let url = self.request.get_url().to_string();
+        self.session_guard
+            .as_deref_mut()?
+            .take_buffered_response(&url)
+    }
+
+    /// Render the page expired error page, else 410 Gone.
+    fn page_expired_handler(&self) -> Box<dyn RequestHandler> {
+        match self.app.page_settings.page_expired_error_page {
+            Some(page_type) => Box::new(RenderPageRequestHandler::new(PageProvider::new(
+                page_type, None,
+            ))),
+            None => Box::new(ErrorCodeRequestHandler::new(
+                410,
+                Some("The page has expired.".to_string()),
+            )),
+        }
+    }
+
+    /// Hand the completed response over to the protocol bridge.
+    pub(crate) fn take_response(&mut self) -> Response {
+        std::mem::take(&mut self.response)
     }
 
     /// For each mapper, construct a handler to derive a compatibility_score.
@@ -77,12 +248,9 @@ impl<'a> RequestCycle<'a> {
         self.app
             .app_request_mappers
             .read()
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Error accessing app_request_mappers for handler resolution! {}",
-                    e
-                )
-            })
+            // The mappers are not changed by a request, a panic while mounting leaves
+            // them usable.
+            .unwrap_or_else(PoisonError::into_inner)
             .iter()
             // Generate a RequestMappingResult for each mapper.
             .filter_map(|mapper| mapper.map_request(request))
@@ -93,44 +261,406 @@ impl<'a> RequestCycle<'a> {
     }
 
     /// Reverse mapping: finding a URL for a Page or Resource.
-    /// Usually, the first mapper that provides a non-none URL wins
+    /// The mapper added most recently that provides a URL wins, so application mounts
+    /// take precedence over the default mappers.
     pub fn map_url_for(&self, handler: &dyn RequestHandler) -> Option<Url> {
         self.app
             .app_request_mappers
             .read()
-            .unwrap_or_else(|e| {
-                panic!(
-                    "Error accessing app_request_mappers for reverse mapping! {}",
-                    e
-                )
-            })
+            .unwrap_or_else(PoisonError::into_inner)
             .iter()
+            .rev()
             .find_map(|mapper| mapper.map_handler(handler))
     }
 
-    /// Store the mutex in the provider and the mutex guard in the request cycle.
-    pub async fn get_session_mut(
-        &mut self,
-        session_provider: &'a mut SessionProvider,
-    ) -> Option<&mut SessionData> {
+    /// The form parameters of the request body, parsed on first access with the
+    /// application multipart settings.
+    pub fn get_post_parameters(&mut self) -> std::result::Result<&PostParameters, MultipartError> {
+        let params = match self.post_parameters.take() {
+            Some(params) => params,
+            None => self
+                .request
+                .get_post_parameters(&self.app.multipart_settings)?,
+        };
+        Ok(self.post_parameters.insert(params))
+    }
+
+    /// Lock the session of the request on first access, it stays locked until the
+    /// cycle is dropped.
+    pub async fn get_session_mut(&mut self) -> Option<&mut SessionData> {
         if self.session_guard.is_none() {
-            // Initial lock: Fetch from DashMap and Lock Mutex
             let session_id = self.request.get_session_id()?;
-            let handle = self
-                .app
+            let registry = self.app.get_session_registry();
+            let Some(guard) = registry.lock_session(session_id).await else {
+                // The session is gone, clear the stale cookie.
+                self.session_cookie.get_or_insert(SessionCookie::Expire);
+                return None;
+            };
+            self.session_guard = Some(guard);
+            self.session_id = Some(session_id);
+        }
+        self.session_guard.as_deref_mut()
+    }
+
+    /// The session of the request, a new session is created when there is none and its
+    /// id sent with the response. The session of the request cookie is locked by
+    /// process_request before the handlers run.
+    pub fn get_or_create_session(&mut self) -> &mut SessionData {
+        if self.session_guard.is_none() {
+            self.create_session();
+        }
+        self.session_guard
+            .as_deref_mut()
+            .expect("Session was just created?")
+    }
+
+    /// The id of the locked session.
+    pub fn get_session_id(&self) -> Option<SessionId> {
+        self.session_id
+    }
+
+    /// Move the session to a new id sent with the response, return the new id. Call
+    /// after a login to defeat session fixation, the old id is no longer valid.
+    /// A session is created when there is none.
+    pub fn replace_session(&mut self) -> SessionId {
+        let registry = self.app.get_session_registry();
+        match self
+            .session_id
+            .and_then(|id| registry.replace_session_id(id))
+        {
+            Some(session_id) => {
+                self.session_id = Some(session_id);
+                self.session_cookie = Some(SessionCookie::Issue(session_id));
+                session_id
+            }
+            None => self.create_session(),
+        }
+    }
+
+    /// Remove the session and expire the session cookie, the session listeners are
+    /// notified.
+    pub fn invalidate_session(&mut self) {
+        if let (Some(session_id), Some(session)) =
+            (self.session_id.take(), self.session_guard.as_deref_mut())
+        {
+            self.app
                 .get_session_registry()
-                .get_session_handle(session_id)?;
-
-            session_provider.session_handle = Some(handle);
-            self.session_guard = Some(
-                session_provider
-                    .session_handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .await,
-            );
+                .invalidate_session(session_id, session);
+        }
+        self.session_guard = None;
+        self.session_cookie = Some(SessionCookie::Expire);
+    }
+
+    fn create_session(&mut self) -> SessionId {
+        let registry = self.app.get_session_registry();
+        let page_store_settings = &self.app.page_store_settings;
+        let session_id =
+            registry.new_session_with(|id| page_store::new_page_store(id, page_store_settings));
+        let handle = registry
+            .get_session_handle(session_id)
+            .expect("Session was just created?");
+        // Nobody else knows the new id yet.
+        let guard = handle
+            .try_lock_owned()
+            .expect("New session is already locked?");
+        self.session_guard = Some(guard);
+        self.session_id = Some(session_id);
+        self.session_cookie = Some(SessionCookie::Issue(session_id));
+        session_id
+    }
+
+    /// Hand back the handle of the page instance version read by the handler. A changed
+    /// page is stored in the session as a new version once the handler completes, unless
+    /// the session was invalidated.
+    pub fn set_page_handle(&mut self, page_id: u16, version: u16, handle: PageHandle<'_>) {
+        if handle.is_dirty() {
+            self.dirty_pages
+                .push((page_id, version, handle.into_owned()));
+        }
+    }
+
+    pub(crate) fn store_dirty_pages(&mut self) -> Result<()> {
+        let dirty_pages = std::mem::take(&mut self.dirty_pages);
+        let Some(session) = self.session_guard.as_deref_mut() else {
+            return Ok(());
+        };
+        for (page_id, version, page) in dirty_pages {
+            // An instance gone from the store is not recreated.
+            session.store_page(page_id, version, page)?;
+        }
+        Ok(())
+    }
+
+    /// Add the Set-Cookie header when the request created, replaced or invalidated
+    /// the session.
+    pub(crate) fn set_session_cookie(&mut self) {
+        let Some(session_cookie) = self.session_cookie.take() else {
+            return;
+        };
+        let settings = &self.app.session_cookie_settings;
+        let value = match session_cookie {
+            SessionCookie::Issue(session_id) => session_id.to_string(),
+            SessionCookie::Expire => String::new(),
+        };
+        let mut cookie = Cookie::build((SESSION_ID_COOKIE_NAME, value))
+            .http_only(settings.http_only)
+            .secure(settings.secure)
+            .same_site(settings.same_site)
+            .path(settings.path.clone());
+        match session_cookie {
+            SessionCookie::Issue(_) => {
+                if let Some(max_age) = settings.max_age {
+                    cookie = cookie.max_age(Duration::seconds(max_age.as_secs() as i64));
+                }
+            }
+            SessionCookie::Expire => cookie = cookie.removal(),
         }
+        self.response
+            .add_header("Set-Cookie", cookie.build().to_string());
+    }
+
+    /// The locale of the session, else the Accept-Language preference supported by the
+    /// markup.
+    pub fn get_locale(&self) -> Option<Locale> {
+        if let Some(locale) = self.get_session().and_then(SessionData::get_locale) {
+            return Some(locale.clone());
+        }
+        let accept_language = self
+            .request
+            .parts
+            .headers
+            .get(http::header::ACCEPT_LANGUAGE)?
+            .to_str()
+            .ok()?;
+        self.app.html_dimensions.negotiate_locale(accept_language)
+    }
+
+    /// The markup selected by the locale, style and variation of the request.
+    pub fn get_markup_dimensions(&self) -> MarkupDimensions {
+        let session = self.get_session();
+        self.app.html_dimensions.resolve(
+            self.get_locale().as_ref(),
+            session.and_then(SessionData::get_style),
+            session.and_then(SessionData::get_variation),
+        )
+    }
+
+    /// The session locked for the request, None when the request has no session.
+    pub fn get_session(&self) -> Option<&SessionData> {
+        self.session_guard.as_deref()
+    }
+
+    /// The session locked by [RequestCycle::get_session_mut], for use in handlers.
+    pub fn get_locked_session(&mut self) -> Option<&mut SessionData> {
         self.session_guard.as_deref_mut()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, RwLock};
+    use std::time::{Duration, Instant};
+
+    use async_trait::async_trait;
+    use cookie::SameSite;
+    use wicket_request::request::url::Url;
+
+    use crate::components::WebPage;
+    use crate::protocol::http::WebApplication;
+    use crate::request::handler::PageProvider;
+    use crate::request::{
+        Request, RequestHandler, RequestMapper, RequestMapperLogic, RequestMappingResult,
+    };
+    use crate::session::SessionId;
+    use crate::settings::SessionCookieSettings;
+    use crate::test_util::{product_app, request, session_cookie, CurrentUser};
+
+    use super::{HandlerResult, RequestCycle};
+
+    fn cycle(app: &Arc<WebApplication>, session_id: Option<SessionId>) -> RequestCycle {
+        app.create_request_cycle(request("/", session_id))
+    }
+
+    fn set_cookie(cycle: &mut RequestCycle) -> Option<String> {
+        cycle.set_session_cookie();
+        cycle.response.get_header("Set-Cookie").map(str::to_string)
+    }
+
+    #[test]
+    pub fn session_cookie_test() {
+        let app = Arc::new(WebApplication {
+            session_cookie_settings: SessionCookieSettings {
+                secure: true,
+                same_site: SameSite::Strict,
+                path: "/app".to_string(),
+                max_age: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let mut new_cycle = cycle(&app, None);
+        new_cycle.get_or_create_session();
+        let session_id = new_cycle.get_session_id().unwrap();
+        let header = set_cookie(&mut new_cycle).unwrap();
+        assert_eq!(
+            format!(
+                "SESSION_ID={}; HttpOnly; SameSite=Strict; Secure; Path=/app; Max-Age=3600",
+                session_id
+            ),
+            header
+        );
+        drop(new_cycle);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // A known session is not sent again.
+            let mut known = cycle(&app, Some(session_id));
+            assert!(known.get_session_mut().await.is_some());
+            assert_eq!(None, set_cookie(&mut known));
+            drop(known);
+
+            // The id of a fixated session stops working.
+            let mut login = cycle(&app, Some(session_id));
+            login.get_session_mut().await;
+            let new_id = login.replace_session();
+            assert_ne!(session_id, new_id);
+            assert!(set_cookie(&mut login)
+                .unwrap()
+                .starts_with(&format!("SESSION_ID={};", new_id)));
+            drop(login);
+            assert!(app.sessions.get_session_handle(session_id).is_none());
+            assert!(app.sessions.get_session_handle(new_id).is_some());
+
+            let mut logout = cycle(&app, Some(new_id));
+            logout.get_session_mut().await;
+            logout.invalidate_session();
+            assert!(set_cookie(&mut logout).unwrap().contains("Max-Age=0"));
+            assert!(app.sessions.get_session_handle(new_id).is_none());
+
+            // A stale cookie is cleared.
+            let mut stale = cycle(&app, Some(new_id));
+            assert!(stale.get_session_mut().await.is_none());
+            assert!(set_cookie(&mut stale).unwrap().starts_with("SESSION_ID=;"));
+        });
+    }
+
+    // Blocks its worker thread until the handlers of both requests have started, which
+    // only happens when they run in parallel.
+    struct RendezvousHandler {
+        arrived: Arc<AtomicUsize>,
+    }
+    #[async_trait]
+    impl RequestHandler for RendezvousHandler {
+        async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+            assert!(cycle.get_session().is_some());
+            self.arrived.fetch_add(1, Ordering::SeqCst);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.arrived.load(Ordering::SeqCst) < 2 {
+                if Instant::now() > deadline {
+                    return Err(std::io::Error::other("The requests ran one at a time."));
+                }
+                std::thread::yield_now();
+            }
+            Ok(HandlerResult::Complete)
+        }
+
+        fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+            &None
+        }
+
+        fn as_page_provider(&self) -> &Option<PageProvider> {
+            &None
+        }
+    }
+
+    struct RendezvousMapper {
+        arrived: Arc<AtomicUsize>,
+    }
+    impl RequestMapperLogic for RendezvousMapper {
+        fn map_request(&self, _request: &Request) -> Option<RequestMappingResult> {
+            Some(RequestMappingResult {
+                handler: Box::new(RendezvousHandler {
+                    arrived: self.arrived.clone(),
+                }),
+                compatibility_score: 1,
+            })
+        }
+
+        fn map_handler(&self, _handler: &dyn RequestHandler) -> Option<Url> {
+            None
+        }
+    }
+
+    #[test]
+    pub fn parallel_sessions_test() {
+        let mapper = RendezvousMapper {
+            arrived: Arc::new(AtomicUsize::new(0)),
+        };
+        let app = Arc::new(WebApplication {
+            app_request_mappers: RwLock::new(vec![RequestMapper::Custom(Box::new(mapper))]),
+            ..Default::default()
+        });
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+        let statuses = runtime.block_on(async {
+            let requests: Vec<_> = (0..2)
+                .map(|_| {
+                    let app = app.clone();
+                    let request = cycle(&app, Some(app.sessions.new_session())).request;
+                    tokio::spawn(async move { app.process_request(request).await })
+                })
+                .collect();
+            let mut statuses = Vec::new();
+            for request in requests {
+                statuses.push(request.await.unwrap().unwrap().status);
+            }
+            statuses
+        });
+        assert_eq!(vec![200, 200], statuses);
+    }
+
+    #[tokio::test]
+    pub async fn listener_session_test() {
+        let app = product_app(WebApplication::default());
+
+        let response = app
+            .process_request(request("/product/5", None))
+            .await
+            .unwrap();
+        let anonymous = session_cookie(&response).unwrap();
+
+        // The login stores the user and rotates the session id.
+        let response = app
+            .process_request(request(
+                "/product/5?0-0.submit-form-login&user=7",
+                Some(anonymous),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(302, response.status);
+        let user = session_cookie(&response).unwrap();
+        assert_ne!(anonymous, user);
+        {
+            let handle = app.sessions.get_session_handle(user).unwrap();
+            let mut session = handle.lock().await;
+            assert_eq!(Some(&CurrentUser(7)), session.get::<CurrentUser>());
+            assert!(session.get_page(0, 1).unwrap().is_some());
+        }
+
+        let response = app
+            .process_request(request("/product/5?0.submit-form-logout", Some(user)))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert!(response.get_header("Set-Cookie").is_some());
+        assert_eq!(None, session_cookie(&response));
+        assert!(app.sessions.get_session_handle(user).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::request::cycle::RequestCycle;
+use crate::request::exception::RequestFailure;
+use crate::request::RequestHandler;
+
+/// Port of IRequestCycleListener: application hooks into the processing of each
+/// request eg a database transaction per request, audit logging or timing, see
+/// [crate::protocol::http::WebApplication::add_request_cycle_listener].
+///
+/// The listeners are called in the order they were added. State of the request is
+/// kept in the cycle meta data eg the start time in on_begin_request read back in
+/// on_end_request, see [RequestCycle::set_meta_data].
+pub trait RequestCycleListener: Send + Sync {
+    /// The session of the request is locked, the handler is not yet resolved.
+    fn on_begin_request(&self, _cycle: &mut RequestCycle) {}
+
+    /// The handler mapped from the url, before it responds. Not called when the render
+    /// buffered by [crate::settings::RenderStrategy::RedirectToBuffer] is served, the
+    /// buffer is served without resolving a handler.
+    fn on_request_handler_resolved(
+        &self,
+        _cycle: &mut RequestCycle,
+        _handler: &dyn RequestHandler,
+    ) {
+    }
+
+    /// The handler responded, called for the resolved handler and for each handler it
+    /// schedules eg the redirect after a listener. Not called when a buffered render is
+    /// served instead of the handler.
+    fn on_request_handler_executed(
+        &self,
+        _cycle: &mut RequestCycle,
+        _handler: &dyn RequestHandler,
+    ) {
+    }
+
+    /// A handler failed or no handler was found. Every listener is called eg to roll
+    /// back a transaction, the first handler returned responds instead of the
+    /// application [crate::request::exception::ExceptionMapper].
+    fn on_exception(
+        &self,
+        _cycle: &mut RequestCycle,
+        _failure: &RequestFailure,
+    ) -> Option<Box<dyn RequestHandler>> {
+        None
+    }
+
+    /// The response is complete, including the response to a failure.
+    fn on_end_request(&self, _cycle: &mut RequestCycle) {}
+
+    /// Called after on_end_request of every listener to release the resources of the
+    /// request, also when the request failed.
+    fn on_detach(&self, _cycle: &mut RequestCycle) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::protocol::http::WebApplication;
+    use crate::request::cycle::RequestCycle;
+    use crate::request::exception::RequestFailure;
+    use crate::request::handler::ErrorCodeRequestHandler;
+    use crate::request::RequestHandler;
+    use crate::test_util::{product_app, request};
+
+    use super::RequestCycleListener;
+
+    // Records the hooks called, the begin time is kept in the cycle meta data.
+    struct RecordingListener {
+        events: Arc<Mutex<Vec<String>>>,
+        exception_status: Option<u16>,
+    }
+    struct BeginTime(std::time::Instant);
+    impl RequestCycleListener for RecordingListener {
+        fn on_begin_request(&self, cycle: &mut RequestCycle) {
+            cycle.set_meta_data(BeginTime(std::time::Instant::now()));
+            self.events.lock().unwrap().push("begin".to_string());
+        }
+
+        fn on_request_handler_resolved(
+            &self,
+            _cycle: &mut RequestCycle,
+            _handler: &dyn RequestHandler,
+        ) {
+            self.events.lock().unwrap().push("resolved".to_string());
+        }
+
+        fn on_request_handler_executed(
+            &self,
+            _cycle: &mut RequestCycle,
+            _handler: &dyn RequestHandler,
+        ) {
+            self.events.lock().unwrap().push("executed".to_string());
+        }
+
+        fn on_exception(
+            &self,
+            _cycle: &mut RequestCycle,
+            failure: &RequestFailure,
+        ) -> Option<Box<dyn RequestHandler>> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("exception {}", failure.status()));
+            self.exception_status
+                .map(|status| Box::new(ErrorCodeRequestHandler::new(status, None)) as _)
+        }
+
+        fn on_end_request(&self, cycle: &mut RequestCycle) {
+            let begin = cycle.get_meta_data::<BeginTime>().unwrap().0;
+            assert!(begin.elapsed() < std::time::Duration::from_secs(60));
+            self.events.lock().unwrap().push("end".to_string());
+        }
+
+        fn on_detach(&self, cycle: &mut RequestCycle) {
+            assert!(cycle.get_meta_data::<BeginTime>().is_some());
+            self.events.lock().unwrap().push("detach".to_string());
+        }
+    }
+
+    #[tokio::test]
+    pub async fn request_cycle_listener_test() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut app = WebApplication::default();
+        app.add_request_cycle_listener(RecordingListener {
+            events: events.clone(),
+            exception_status: None,
+        });
+        app.add_request_cycle_listener(RecordingListener {
+            events: Arc::new(Mutex::new(Vec::new())),
+            exception_status: Some(503),
+        });
+        let app = product_app(app);
+        let session_id = app.sessions.new_session();
+        let take_events = || std::mem::take(&mut *events.lock().unwrap()).join(", ");
+        let process = |uri: &str| app.process_request(request(uri, Some(session_id)));
+
+        let response = process("/product/5").await.unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!("begin, resolved, executed, end, detach", take_events());
+
+        // The listener handler schedules the redirect.
+        let response = process("/product/5?0-0.submit-form-save").await.unwrap();
+        assert_eq!(302, response.status);
+        assert_eq!(
+            "begin, resolved, executed, executed, end, detach",
+            take_events()
+        );
+        // The buffered render is served without resolving a handler.
+        let location = response.get_header("Location").unwrap().to_string();
+        let response = process(&location).await.unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!("begin, end, detach", take_events());
+
+        // The handler of the second listener responds to the failure.
+        let response = process("/product/5?0-0.click-form-fail").await.unwrap();
+        assert_eq!(503, response.status);
+        assert_eq!(
+            "begin, resolved, exception 500, executed, end, detach",
+            take_events()
+        );
+        let response = process("/no/such/page").await.unwrap();
+        assert_eq!(503, response.status);
+        assert_eq!("begin, exception 404, executed, end, detach", take_events());
+    }
+}
@@ -0,0 +1,244 @@
+use std::io::ErrorKind;
+
+use async_trait::async_trait;
+
+use crate::components::{MarkupType, WebPage};
+use crate::request::cycle::{HandlerResult, RequestCycle};
+use crate::request::handler::{render_page, ErrorCodeRequestHandler, PageProvider};
+use crate::request::RequestHandler;
+
+/// Why a request could not be completed, see [ExceptionMapper].
+#[derive(Debug, thiserror::Error)]
+pub enum RequestFailure {
+    /// No mapper accepts the url.
+    #[error("No handler found for the url.")]
+    NoHandler,
+    /// A handler returned an error.
+    #[error("{0}")]
+    Error(#[from] std::io::Error),
+    /// A handler panicked, with the panic message.
+    #[error("Panic: {0}")]
+    Panic(String),
+}
+
+impl RequestFailure {
+    /// The status of the response: 404 for a url without a handler or a missing
+    /// resource, 403 for a request denied eg a redirect to a host not allowed and 500
+    /// otherwise.
+    pub fn status(&self) -> u16 {
+        match self {
+            RequestFailure::NoHandler => 404,
+            RequestFailure::Error(e) if e.kind() == ErrorKind::NotFound => 404,
+            RequestFailure::Error(e) if e.kind() == ErrorKind::PermissionDenied => 403,
+            RequestFailure::Error(_) | RequestFailure::Panic(_) => 500,
+        }
+    }
+
+    /// The message of a panic payload.
+    pub(crate) fn from_panic(payload: Box<dyn std::any::Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Unknown panic payload.".to_string(),
+            },
+        };
+        RequestFailure::Panic(message)
+    }
+}
+
+impl From<RequestFailure> for std::io::Error {
+    fn from(failure: RequestFailure) -> Self {
+        match failure {
+            RequestFailure::NoHandler => std::io::Error::new(ErrorKind::NotFound, failure),
+            RequestFailure::Error(e) => e,
+            RequestFailure::Panic(_) => std::io::Error::other(failure),
+        }
+    }
+}
+
+/// Port of IExceptionMapper: the handler of the response to a failed request. The
+/// response is reset and the changed pages are discarded before the handler responds.
+pub trait ExceptionMapper: Send + Sync {
+    fn map(&self, failure: &RequestFailure, cycle: &RequestCycle) -> Box<dyn RequestHandler>;
+}
+
+/// Render the error page of the status from the [crate::settings::ExceptionSettings],
+/// else respond with the status alone. In development mode the failure is described
+/// instead.
+#[derive(Default)]
+pub struct DefaultExceptionMapper {}
+
+impl ExceptionMapper for DefaultExceptionMapper {
+    fn map(&self, failure: &RequestFailure, cycle: &RequestCycle) -> Box<dyn RequestHandler> {
+        let settings = &cycle.app.exception_settings;
+        let status = failure.status();
+        if settings.development_mode {
+            let reason = http::StatusCode::from_u16(status)
+                .ok()
+                .and_then(|s| s.canonical_reason())
+                .unwrap_or_default();
+            let message = format!(
+                "{} {}\n{} {}\n\n{}\n",
+                status, reason, cycle.request.parts.method, cycle.request.parts.uri, failure
+            );
+            return Box::new(ErrorCodeRequestHandler::new(status, Some(message)));
+        }
+
+        let error_page = match status {
+            403 => settings.access_denied_page,
+            404 => settings.not_found_page,
+            _ => settings.internal_error_page,
+        };
+        match error_page {
+            Some(page_type) => Box::new(ErrorPageRequestHandler::new(status, page_type)),
+            None => Box::new(ErrorCodeRequestHandler::new(status, None)),
+        }
+    }
+}
+
+/// Render an error page with the status of the failure. The page is rendered without
+/// storing it, a request without a session eg to an unknown url does not create one.
+pub struct ErrorPageRequestHandler {
+    status: u16,
+    // Always Some, held as an Option for as_page_provider().
+    page_provider: Option<PageProvider>,
+}
+
+impl ErrorPageRequestHandler {
+    /// Render the page of the type with the status.
+    pub fn new(status: u16, page_type: &'static MarkupType) -> Self {
+        Self {
+            status,
+            page_provider: Some(PageProvider::new(page_type, None)),
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ErrorPageRequestHandler {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let provider = self.page_provider.as_ref().expect("No PageProvider?");
+        let page = provider.new_page()?;
+        cycle.response.status = self.status;
+        let dimensions = cycle.get_markup_dimensions();
+        render_page(page.as_ref(), dimensions, &mut cycle.response)
+    }
+
+    fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+        &None
+    }
+
+    fn as_page_provider(&self) -> &Option<PageProvider> {
+        &self.page_provider
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Error, ErrorKind};
+
+    use crate::components::StaticMarkupIdentifier;
+    use crate::protocol::http::WebApplication;
+    use crate::request::ResponseBody;
+    use crate::settings::ExceptionSettings;
+    use crate::test_util::{body, product_app, request, ProductPage, PRODUCT_HTML};
+
+    use super::RequestFailure;
+
+    #[test]
+    pub fn request_failure_status_test() {
+        assert_eq!(404, RequestFailure::NoHandler.status());
+        assert_eq!(
+            404,
+            RequestFailure::from(Error::from(ErrorKind::NotFound)).status()
+        );
+        let denied = Error::new(ErrorKind::PermissionDenied, "Redirect not allowed.");
+        assert_eq!(403, RequestFailure::from(denied).status());
+        assert_eq!(500, RequestFailure::from(Error::other("io")).status());
+
+        let panic = std::panic::catch_unwind(|| panic!("Boom {}", 1)).unwrap_err();
+        let failure = RequestFailure::from_panic(panic);
+        assert_eq!(500, failure.status());
+        assert_eq!("Panic: Boom 1", failure.to_string());
+        let panic = std::panic::catch_unwind(|| panic!("Boom")).unwrap_err();
+        assert_eq!("Panic: Boom", RequestFailure::from_panic(panic).to_string());
+    }
+
+    #[tokio::test]
+    pub async fn error_page_test() {
+        let app = product_app(WebApplication {
+            exception_settings: ExceptionSettings {
+                not_found_page: Some(ProductPage::markup_type()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        // The error page is rendered without a session.
+        let response = app
+            .process_request(request("/no/such/page", None))
+            .await
+            .unwrap();
+        assert_eq!(404, response.status);
+        assert!(response.get_headers().is_none());
+        assert_eq!(PRODUCT_HTML, body(response));
+        assert!(app.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn exception_mapper_test() {
+        let app = product_app(WebApplication::default());
+        let session_id = app.sessions.new_session();
+        let process = |uri: &str| app.process_request(request(uri, Some(session_id)));
+
+        let response = process("/no/such/page").await.unwrap();
+        assert_eq!(404, response.status);
+        // The panic of the listener is a 500, the session remains usable.
+        process("/product/5").await.unwrap();
+        let response = process("/product/5?0-0.click-form-fail").await.unwrap();
+        assert_eq!(500, response.status);
+        assert!(matches!(response.get_body(), ResponseBody::Empty));
+        let response = process("/product/5?0").await.unwrap();
+        assert_eq!(200, response.status);
+        {
+            let handle = app.sessions.get_session_handle(session_id).unwrap();
+            let session = handle.lock().await;
+            assert_eq!(Some(0), session.get_latest_version(0));
+        }
+
+        let error_pages = product_app(WebApplication {
+            exception_settings: ExceptionSettings {
+                internal_error_page: Some(ProductPage::markup_type()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let session_id = error_pages.sessions.new_session();
+        let process = |uri: &str| error_pages.process_request(request(uri, Some(session_id)));
+        process("/product/5").await.unwrap();
+        let response = process("/product/5?0-0.click-form-fail").await.unwrap();
+        assert_eq!(500, response.status);
+        assert_eq!(PRODUCT_HTML, body(response));
+        let response = process("/no/such/page").await.unwrap();
+        assert_eq!(404, response.status);
+        assert!(matches!(response.get_body(), ResponseBody::Empty));
+
+        let development = product_app(WebApplication {
+            exception_settings: ExceptionSettings {
+                development_mode: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let session_id = development.sessions.new_session();
+        let process = |uri: &str| development.process_request(request(uri, Some(session_id)));
+        process("/product/5").await.unwrap();
+        let response = process("/product/5?0-0.click-form-fail").await.unwrap();
+        assert_eq!(500, response.status);
+        assert_eq!(
+            "500 Internal Server Error\nGET /product/5?0-0.click-form-fail\n\nPanic: Listener failed.\n",
+            body(response)
+        );
+    }
+}
@@ -1,18 +1,36 @@
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use wicket_request::request::mapper::info::ComponentInfo;
 use wicket_request::request::mapper::parameter::PageParameters;
 
 use crate::{
     components::{MarkupType, PageHandle, WebPage},
+    core::util::resource::locator::ResourceStreamLocator,
+    markup::dimensions::MarkupDimensions,
     request::{
-        cycle::{RedirectAction, SessionProvider},
-        RequestHandler,
+        cycle::{HandlerResult, RedirectAction, RequestCycle},
+        range::{
+            entity_tag, if_none_match_matches, if_range_matches, not_modified_since,
+            parse_range_header, RangeRequest, RangesReader,
+        },
+        RequestHandler, Response, ResponseBody,
+    },
+    session::{
+        page_factory::{construct_page_type, get_page_entry},
+        SessionData,
     },
-    session::{page_factory::construct_page_type, SessionData},
+    settings::{PageSettings, RenderStrategy},
 };
 
 /// Fresh creation: page_type, params.
 /// Identity: page_id, render_id.
+#[derive(Clone)]
 pub struct PageProvider {
-    pub page_type: &'static MarkupType,
+    // None when the page is only known by its page_id eg "/wicket/page?3".
+    pub page_type: Option<&'static MarkupType>,
     // The data taken to construct the page.
     pub params: Option<PageParameters>,
     // The instance of a page, caters to multiple tabs.
@@ -22,61 +40,1058 @@ pub struct PageProvider {
 }
 
 impl PageProvider {
+    /// A new page of the type constructed from the parameters.
     pub fn new(page_type: &'static MarkupType, params: Option<PageParameters>) -> Self {
         Self {
-            page_type,
+            page_type: Some(page_type),
             params,
             page_id: None,
             render_id: None,
         }
     }
 
-    pub fn get_page_handle<'a>(&mut self, session: &'a SessionData) -> Option<PageHandle<'a>> {
+    /// Provide a page instance stored in the session, the most recent version when
+    /// render_id is None.
+    pub fn for_instance(page_id: u16, render_id: Option<u16>) -> Self {
+        Self {
+            page_type: None,
+            params: None,
+            page_id: Some(page_id),
+            render_id,
+        }
+    }
+
+    /// The page instance read through the session page store, else a new page of the
+    /// page type. Hand a changed handle back with [RequestCycle::set_page_handle].
+    pub fn get_page_handle<'a>(
+        &self,
+        session: &'a mut SessionData,
+    ) -> std::io::Result<Option<PageHandle<'a>>> {
         let dirty = false;
         match self.page_id {
             Some(instance_id) => {
-                let wpo = session.get_page(instance_id, self.render_id.unwrap_or(0));
-                wpo.map(|page| PageHandle::Borrowed { page, dirty })
+                let wpo = match self.render_id {
+                    Some(render_id) => session.get_page(instance_id, render_id)?,
+                    None => session.get_latest_page(instance_id)?,
+                };
+                Ok(wpo.map(|page| PageHandle::Borrowed { page, dirty }))
             }
             None => {
-                let p = construct_page_type(self.page_type, self.params.take());
-                p.map(|page| PageHandle::Owned { page, dirty })
+                let p = self
+                    .page_type
+                    .and_then(|page_type| construct_page_type(page_type, self.params.clone()));
+                Ok(p.map(|page| PageHandle::Owned { page, dirty }))
             }
         }
     }
 
+    /// The version of the stored page instance, the most recent when render_id is None.
+    pub fn get_page_version(&self, session: &SessionData) -> Option<u16> {
+        self.render_id
+            .or_else(|| session.get_latest_version(self.page_id?))
+    }
+
     pub fn get_instance(&mut self) -> Option<Box<dyn WebPage>> {
-        construct_page_type(self.page_type, self.params.take())
+        construct_page_type(self.page_type?, self.params.take())
     }
 
     pub fn needs_session_lookup(&self) -> bool {
         self.page_id.is_some()
     }
+
+    /// Construct a new page from the page type and params.
+    pub(crate) fn new_page(&self) -> std::io::Result<Box<dyn WebPage>> {
+        let page_type = self.page_type.ok_or_else(|| self.page_expired())?;
+        construct_page_type(page_type, self.params.clone()).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Page {} is not registered.", page_type.name),
+            )
+        })
+    }
+
+    fn page_expired(&self) -> Error {
+        PageExpiredError {
+            page_id: self.page_id,
+            render_id: self.render_id,
+        }
+        .into()
+    }
+
+    /// Recover from the expiry of the page instance by rendering a new page of the page
+    /// type, see [PageSettings::recreate_bookmarkable_pages_after_expiry].
+    fn recreate_expired(&self, settings: &PageSettings) -> std::io::Result<HandlerResult> {
+        match self.page_type {
+            Some(page_type) if settings.recreate_bookmarkable_pages_after_expiry => {
+                let provider = PageProvider::new(page_type, self.params.clone());
+                Ok(HandlerResult::Schedule(Box::new(
+                    RenderPageRequestHandler::new(provider),
+                )))
+            }
+            _ => Err(self.page_expired()),
+        }
+    }
+}
+
+/// The page instance of a url is no longer in the session page store eg the session
+/// expired or the version was evicted. The request cycle renders the
+/// [PageSettings::page_expired_error_page].
+#[derive(Debug, thiserror::Error)]
+#[error("Page instance {page_id:?} version {render_id:?} has expired.")]
+pub struct PageExpiredError {
+    pub page_id: Option<u16>,
+    pub render_id: Option<u16>,
+}
+
+impl PageExpiredError {
+    /// The PageExpiredError of a handler error.
+    pub fn find(error: &Error) -> Option<&PageExpiredError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl From<PageExpiredError> for Error {
+    fn from(error: PageExpiredError) -> Self {
+        Error::new(ErrorKind::NotFound, error)
+    }
+}
+
+pub(crate) fn render_page(
+    page: &dyn WebPage,
+    dimensions: MarkupDimensions,
+    response: &mut Response,
+) -> std::io::Result<HandlerResult> {
+    let MarkupDimensions {
+        style,
+        variation,
+        lang,
+        country,
+    } = dimensions;
+    let markup_resource = page.lookup_markup(style, variation, lang, country);
+    let markup_resource = markup_resource.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("No markup for page {}.", page.get_markup_identity().name),
+        )
+    })?;
+
+    response.set_content_type("text/html; charset=utf-8");
+    response.set_body(ResponseBody::Buffered(Vec::with_capacity(32 * 1024)));
+    markup_resource.markup.render(response, page)?;
+    Ok(HandlerResult::Complete)
+}
+
+/// Render a page into the response, the target of mounted and bookmarkable urls.
+/// A new page is stored in the session, created on demand, so later requests can
+/// address the instance by its page id.
+pub struct RenderPageRequestHandler {
+    // Always Some, held as an Option for as_page_provider().
+    page_provider: Option<PageProvider>,
+}
+
+impl RenderPageRequestHandler {
+    /// Render the page of the provider.
+    pub fn new(page_provider: PageProvider) -> Self {
+        Self {
+            page_provider: Some(page_provider),
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for RenderPageRequestHandler {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let provider = self.page_provider.as_ref().expect("No PageProvider?");
+        if !provider.needs_session_lookup() {
+            cycle.get_or_create_session();
+        }
+        let dimensions = cycle.get_markup_dimensions();
+        let RequestCycle {
+            response,
+            session_guard,
+            app,
+            ..
+        } = cycle;
+        let Some(session) = session_guard.as_deref_mut() else {
+            return provider.recreate_expired(&app.page_settings);
+        };
+
+        let handle;
+        let page = match provider.page_id {
+            Some(_) => match provider.get_page_handle(session)? {
+                Some(page_handle) => {
+                    handle = page_handle;
+                    handle.as_trait()
+                }
+                None => return provider.recreate_expired(&app.page_settings),
+            },
+            None => {
+                let page_id = session.add_page(provider.new_page()?)?;
+                session
+                    .get_page(page_id, 0)?
+                    .expect("Page was just stored?")
+            }
+        };
+        render_page(page, dimensions, response)
+    }
+
+    fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+        &None
+    }
+
+    fn as_page_provider(&self) -> &Option<PageProvider> {
+        &self.page_provider
+    }
+}
+
+/// Invoke a component listener on a page instance eg a link click, then render the
+/// page. The page is copied before the listener runs, the changed copy is stored as
+/// a new version of the instance.
+pub struct ListenerRequestHandler {
+    // Always Some with a page_id, held as an Option for as_page_provider().
+    page_provider: Option<PageProvider>,
+    component_info: ComponentInfo,
 }
 
+impl ListenerRequestHandler {
+    /// Invoke the listener of the component on the page instance of the provider.
+    pub fn new(page_provider: PageProvider, component_info: ComponentInfo) -> Self {
+        Self {
+            page_provider: Some(page_provider),
+            component_info,
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ListenerRequestHandler {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let provider = self.page_provider.as_ref().expect("No PageProvider?");
+        let page_id = provider.page_id.expect("Listener without a page id?");
+        let stored = match cycle.get_locked_session() {
+            Some(session) => match provider.get_page_version(session) {
+                // The copy changed by the listener does not borrow the session of the cycle.
+                Some(version) => provider
+                    .get_page_handle(session)?
+                    .map(|handle| (version, handle.detach())),
+                None => None,
+            },
+            None => None,
+        };
+        let Some((version, mut handle)) = stored else {
+            return provider.recreate_expired(&cycle.app.page_settings);
+        };
+        let action = handle
+            .to_mut()
+            .invoke_listener(
+                &self.component_info.component_path,
+                &self.component_info.listener,
+                cycle,
+            )
+            .await?;
+
+        let strategy = cycle.app.render_strategy;
+        let has_session = cycle.get_session().is_some();
+        let result = match action {
+            // Rendered in place, an ajax update or no session to buffer the page in.
+            RedirectAction::None
+                if strategy == RenderStrategy::OnePassRender
+                    || cycle.request.is_ajax()
+                    || !has_session =>
+            {
+                let dimensions = cycle.get_markup_dimensions();
+                render_page(handle.as_trait(), dimensions, &mut cycle.response)?
+            }
+            RedirectAction::None | RedirectAction::RedirectSelf
+                if strategy == RenderStrategy::RedirectToBuffer && has_session =>
+            {
+                // Rendered now and served to the redirected GET of the page url.
+                let redirect = RedirectHandler::for_page(RedirectAction::RedirectSelf, provider);
+                let url = RedirectHandler::page_url(cycle, redirect.page_provider.clone())?;
+                let dimensions = cycle.get_markup_dimensions();
+                render_page(handle.as_trait(), dimensions, &mut cycle.response)?;
+                let buffered = std::mem::take(&mut cycle.response);
+                if let Some(session) = cycle.get_locked_session() {
+                    session.buffer_response(url, buffered);
+                }
+                HandlerResult::Schedule(Box::new(redirect))
+            }
+            RedirectAction::None => HandlerResult::Schedule(Box::new(RedirectHandler::for_page(
+                RedirectAction::RedirectSelf,
+                provider,
+            ))),
+            // Redirected once the new version is stored.
+            action => {
+                HandlerResult::Schedule(Box::new(RedirectHandler::for_page(action, provider)))
+            }
+        };
+        // Dropped when the listener ended the session eg a logout.
+        cycle.set_page_handle(page_id, version, handle);
+        Ok(result)
+    }
+
+    fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+        &None
+    }
+
+    fn as_page_provider(&self) -> &Option<PageProvider> {
+        &self.page_provider
+    }
+
+    fn get_component_info(&self) -> Option<&ComponentInfo> {
+        Some(&self.component_info)
+    }
+}
+
+/// Redirect the client after a listener, see [RedirectAction].
+///
+/// A GET is redirected with 302 Found, other methods with 303 See Other so the browser
+/// follows with a GET. Ajax requests are answered with an Ajax-Location header for the
+/// client script to navigate.
 pub struct RedirectHandler {
     pub redirect_action: RedirectAction,
+    // The page instance of a RedirectSelf.
+    page_provider: Option<PageProvider>,
 }
 impl From<RedirectAction> for RedirectHandler {
     fn from(redirect_action: RedirectAction) -> Self {
-        Self { redirect_action }
+        Self {
+            redirect_action,
+            page_provider: None,
+        }
+    }
+}
+impl RedirectHandler {
+    /// The redirect of a listener of the page instance, RedirectSelf redirects to the
+    /// most recent version of the instance: post, redirect, get.
+    pub fn for_page(redirect_action: RedirectAction, page_provider: &PageProvider) -> Self {
+        Self {
+            redirect_action,
+            page_provider: Some(PageProvider {
+                render_id: None,
+                ..page_provider.clone()
+            }),
+        }
+    }
+
+    fn page_url(cycle: &RequestCycle, provider: Option<PageProvider>) -> std::io::Result<String> {
+        let provider = provider
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "RedirectSelf without a page."))?;
+        let page_type = provider.page_type.map_or("page instance", |pt| pt.name);
+        cycle
+            .map_url_for(&RenderPageRequestHandler::new(provider))
+            .map(|url| url.to_string())
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No url for {}.", page_type)))
     }
 }
-impl RedirectHandler {}
+#[async_trait]
 impl RequestHandler for RedirectHandler {
-    fn respond(
-        &self,
-        _cycle: &mut super::cycle::RequestCycle,
-        _session_provider: &mut SessionProvider,
-    ) -> std::io::Result<super::cycle::HandlerResult> {
-        todo!()
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let location = match &self.redirect_action {
+            RedirectAction::None => {
+                return Err(Error::new(ErrorKind::InvalidInput, "No redirect."));
+            }
+            RedirectAction::RedirectSelf => Self::page_url(cycle, self.page_provider.clone())?,
+            RedirectAction::Redirect(page) => {
+                let page = dyn_clone::clone_box(page.as_ref());
+                // Registered pages are addressed by their mount, else by the instance.
+                let page_type = get_page_entry(page.get_markup_identity().id).map(|pe| pe.id);
+                let page_id = cycle.get_or_create_session().add_page(page)?;
+                let provider = PageProvider {
+                    page_type,
+                    params: None,
+                    page_id: Some(page_id),
+                    render_id: None,
+                };
+                Self::page_url(cycle, Some(provider))?
+            }
+            RedirectAction::RedirectUrl(url) => {
+                if !cycle.app.redirect_settings.is_allowed(url) {
+                    return Err(Error::new(
+                        ErrorKind::PermissionDenied,
+                        format!("Redirect to {} is not allowed.", url),
+                    ));
+                }
+                url.clone()
+            }
+        };
+
+        let RequestCycle {
+            request, response, ..
+        } = cycle;
+        if request.is_ajax() {
+            response.status = 200;
+            response.set_header("Ajax-Location", location);
+        } else {
+            let method = &request.parts.method;
+            response.status = if method == http::Method::GET || method == http::Method::HEAD {
+                302
+            } else {
+                303
+            };
+            response.set_header("Location", location);
+        }
+        response.set_body(ResponseBody::Empty);
+        Ok(HandlerResult::Complete)
+    }
+
+    fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+        &None
+    }
+
+    fn as_page_provider(&self) -> &Option<PageProvider> {
+        &self.page_provider
+    }
+}
+
+/// Respond with an error status and an optional plain text message eg 404 for a
+/// tampered url.
+pub struct ErrorCodeRequestHandler {
+    status: u16,
+    message: Option<String>,
+}
+
+impl ErrorCodeRequestHandler {
+    /// Respond with the status and the message as a text/plain body, else no body.
+    pub fn new(status: u16, message: Option<String>) -> Self {
+        Self { status, message }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ErrorCodeRequestHandler {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let response = &mut cycle.response;
+        response.status = self.status;
+        match &self.message {
+            Some(message) => {
+                response.set_content_type("text/plain; charset=utf-8");
+                response.set_body(ResponseBody::Buffered(Vec::with_capacity(message.len())));
+                response.write_str(message)?;
+            }
+            None => response.set_body(ResponseBody::Empty),
+        }
+        Ok(HandlerResult::Complete)
+    }
+
+    fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+        &None
+    }
+
+    fn as_page_provider(&self) -> &Option<PageProvider> {
+        &None
+    }
+}
+
+/// Stream a resource to the client, honouring Range, If-Range and the conditional
+/// GET headers If-None-Match and If-Modified-Since.
+/// Resources of known length are sent with a Content-Length, otherwise chunked.
+pub struct ResourceStreamRequestHandler {
+    locator: Arc<dyn ResourceStreamLocator>,
+    path: PathBuf,
+    content_type: Option<String>,
+    cache_control: Option<String>,
+}
+
+impl ResourceStreamRequestHandler {
+    /// Stream the resource of the locator at the path with the content type, without a
+    /// Cache-Control header. The resource is opened on respond, a missing one is a 404.
+    pub fn new(
+        locator: Arc<dyn ResourceStreamLocator>,
+        path: impl Into<PathBuf>,
+        content_type: Option<String>,
+    ) -> Self {
+        Self {
+            locator,
+            path: path.into(),
+            content_type,
+            cache_control: None,
+        }
+    }
+
+    /// The Cache-Control header value sent with the resource and its 304s.
+    pub fn with_cache_control(mut self, cache_control: impl Into<String>) -> Self {
+        self.cache_control = Some(cache_control.into());
+        self
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ResourceStreamRequestHandler {
+    async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+        let locator = self.locator.clone();
+        let path = self.path.clone();
+        // Opening the file blocks, it runs on the blocking threads of the runtime.
+        let (stream, length, last_modified) = tokio::task::spawn_blocking(move || {
+            let stream = locator
+                .locate(&path, &None, &None)
+                .map_err(|e| Error::new(ErrorKind::NotFound, e))?;
+            let (length, last_modified) = (stream.length(), stream.last_modified());
+            Ok::<_, Error>((stream, length, last_modified))
+        })
+        .await
+        .map_err(Error::other)??;
+        let etag = length.zip(last_modified).map(|(l, m)| entity_tag(l, m));
+
+        let request = &cycle.request.parts;
+        let header_str = |name| request.headers.get(name).and_then(|v| v.to_str().ok());
+        let conditional_get =
+            request.method == http::Method::GET || request.method == http::Method::HEAD;
+        // If-Modified-Since is ignored when If-None-Match is present.
+        let not_modified = conditional_get
+            && match header_str(http::header::IF_NONE_MATCH) {
+                Some(value) => if_none_match_matches(value, etag.as_deref()),
+                None => header_str(http::header::IF_MODIFIED_SINCE)
+                    .is_some_and(|value| not_modified_since(value, last_modified)),
+            };
+
+        let response = &mut cycle.response;
+        if let Some(etag) = &etag {
+            response.set_header("ETag", etag.as_str());
+        }
+        if let Some(modified) = last_modified {
+            response.set_header("Last-Modified", httpdate::fmt_http_date(modified));
+        }
+        if let Some(cache_control) = &self.cache_control {
+            response.set_header("Cache-Control", cache_control.as_str());
+        }
+        if not_modified {
+            response.status = 304;
+            response.set_body(ResponseBody::Empty);
+            return Ok(HandlerResult::Complete);
+        }
+
+        let mut reader = stream.into_seekable().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Unsupported,
+                "The resource stream is not readable.",
+            )
+        })?;
+        let if_range_ok = header_str(http::header::IF_RANGE)
+            .is_none_or(|value| if_range_matches(value, etag.as_deref(), last_modified));
+        let range_request = match (length, header_str(http::header::RANGE)) {
+            (Some(length), Some(range)) if if_range_ok => parse_range_header(range, length),
+            _ => RangeRequest::Full,
+        };
+
+        response.set_header("Accept-Ranges", "bytes");
+
+        match range_request {
+            RangeRequest::Full => {
+                response.status = 200;
+                if let Some(ct) = &self.content_type {
+                    response.set_content_type(ct.as_str());
+                }
+                if let Some(length) = length {
+                    response.set_header("Content-Length", length.to_string());
+                }
+                response.set_body(ResponseBody::Streaming(reader));
+            }
+            RangeRequest::Unsatisfiable => {
+                response.status = 416;
+                let complete_length = length.unwrap_or_default();
+                response.set_header("Content-Range", format!("bytes */{}", complete_length));
+                response.set_body(ResponseBody::Empty);
+            }
+            RangeRequest::Satisfiable(ranges) => {
+                let complete_length = length.unwrap_or_default();
+                response.status = 206;
+                if let [range] = ranges.as_slice() {
+                    reader.seek(SeekFrom::Start(range.start))?;
+                    if let Some(ct) = &self.content_type {
+                        response.set_content_type(ct.as_str());
+                    }
+                    response.set_header("Content-Range", range.content_range(complete_length));
+                    response.set_header("Content-Length", range.length().to_string());
+                    response.set_body(ResponseBody::Streaming(Box::new(
+                        reader.take(range.length()),
+                    )));
+                } else {
+                    let (ranges_reader, boundary, body_length) = RangesReader::new(
+                        reader,
+                        &ranges,
+                        self.content_type.as_deref(),
+                        complete_length,
+                    );
+                    response
+                        .set_content_type(format!("multipart/byteranges; boundary={}", boundary));
+                    response.set_header("Content-Length", body_length.to_string());
+                    response.set_body(ResponseBody::Streaming(Box::new(ranges_reader)));
+                }
+            }
+        }
+        Ok(HandlerResult::Complete)
     }
 
     fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
-        todo!()
+        &None
     }
 
     fn as_page_provider(&self) -> &Option<PageProvider> {
-        todo!()
+        &None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::sync::Arc;
+
+    use crate::components::{
+        ComponentId, MarkupContainer, MarkupIdentifier, MarkupLookup, StaticMarkupIdentifier,
+    };
+    use crate::core::util::resource::locator::FileResourceStreamLocator;
+    use crate::markup::dimensions::{Locale, ValidHtmlDimensions};
+    use crate::markup::loader::MarkupResourceLocationUtil;
+    use crate::markup::{Markup, MarkupResource};
+    use crate::protocol::http::WebApplication;
+    use crate::request::Response;
+    use crate::settings::RedirectSettings;
+    use crate::test_util::{body, product_app, request, request_with, ProductPage, PRODUCT_HTML};
+
+    use super::*;
+
+    const RESOURCE_ROOT: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/resources/html/markup/loader/test"
+    );
+
+    // Respond with the test resource "<div>New Component</div>\n" for the request headers.
+    fn respond(headers: &[(&str, &str)]) -> Response {
+        let mut builder = http::Request::builder().uri("/AppComponent.html");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let app = Arc::new(WebApplication::default());
+        let mut cycle = app.create_request_cycle(request_with(builder, None));
+        let root = PathBuf::from(RESOURCE_ROOT);
+        let handler = ResourceStreamRequestHandler::new(
+            Arc::new(FileResourceStreamLocator::new(vec![root])),
+            "AppComponent.html",
+            Some("text/html".to_string()),
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(handler.respond(&mut cycle)).unwrap();
+        cycle.take_response()
+    }
+
+    fn body_string(response: &mut Response) -> String {
+        let mut body = String::new();
+        if let ResponseBody::Streaming(mut read) = response.take_body() {
+            read.read_to_string(&mut body).unwrap();
+        }
+        body
+    }
+
+    #[test]
+    pub fn resource_full_test() {
+        let mut response = respond(&[]);
+        assert_eq!(200, response.status);
+        assert_eq!(Some("25"), response.get_header("Content-Length"));
+        assert_eq!(Some("bytes"), response.get_header("Accept-Ranges"));
+        assert_eq!("<div>New Component</div>\n", body_string(&mut response));
+    }
+
+    #[test]
+    pub fn resource_range_test() {
+        let mut response = respond(&[("Range", "bytes=5-7")]);
+        assert_eq!(206, response.status);
+        assert_eq!(Some("bytes 5-7/25"), response.get_header("Content-Range"));
+        assert_eq!(Some("text/html"), response.get_content_type());
+        assert_eq!("New", body_string(&mut response));
+
+        let mut response = respond(&[("Range", "bytes=0-0,-2")]);
+        assert_eq!(206, response.status);
+        assert!(response
+            .get_content_type()
+            .unwrap()
+            .starts_with("multipart/byteranges; boundary="));
+        let body = body_string(&mut response);
+        assert!(body.contains("Content-Range: bytes 0-0/25\r\n\r\n<\r\n"));
+        assert!(body.contains("Content-Range: bytes 23-24/25\r\n\r\n>\n\r\n"));
+        assert_eq!(
+            Some(body.len().to_string().as_str()),
+            response.get_header("Content-Length")
+        );
+
+        let response = respond(&[("Range", "bytes=100-")]);
+        assert_eq!(416, response.status);
+        assert_eq!(Some("bytes */25"), response.get_header("Content-Range"));
+    }
+
+    #[test]
+    pub fn resource_conditional_get_test() {
+        let full = respond(&[]);
+        let etag = full.get_header("ETag").unwrap().to_string();
+        let modified = full.get_header("Last-Modified").unwrap().to_string();
+
+        let mut response = respond(&[("If-None-Match", etag.as_str())]);
+        assert_eq!(304, response.status);
+        assert_eq!(Some(etag.as_str()), response.get_header("ETag"));
+        assert!(matches!(response.take_body(), ResponseBody::Empty));
+
+        let response = respond(&[("If-Modified-Since", modified.as_str())]);
+        assert_eq!(304, response.status);
+
+        // If-None-Match takes precedence over If-Modified-Since.
+        let response = respond(&[
+            ("If-None-Match", "\"stale\""),
+            ("If-Modified-Since", modified.as_str()),
+        ]);
+        assert_eq!(200, response.status);
+    }
+
+    #[test]
+    pub fn resource_if_range_test() {
+        let etag = respond(&[]);
+        let etag = etag.get_header("ETag").unwrap().to_string();
+
+        let response = respond(&[("Range", "bytes=5-7"), ("If-Range", etag.as_str())]);
+        assert_eq!(206, response.status);
+
+        let mut response = respond(&[("Range", "bytes=5-7"), ("If-Range", "\"stale\"")]);
+        assert_eq!(200, response.status);
+        assert_eq!("<div>New Component</div>\n", body_string(&mut response));
+    }
+
+    // Markup for the dark style and fr locales, sorted by specificity as the macro does.
+    static LOCALIZED_MARKUP: [MarkupResource; 5] = [
+        localized(Some(0), Some(0), None, "dark_fr"),
+        localized(Some(0), None, None, "dark"),
+        localized(None, Some(0), Some(0), "fr_CA"),
+        localized(None, Some(0), None, "fr"),
+        localized(None, None, None, "default"),
+    ];
+
+    const fn localized(
+        style: Option<u8>,
+        lang: Option<u8>,
+        country: Option<u8>,
+        source: &'static str,
+    ) -> MarkupResource {
+        MarkupResource {
+            style,
+            variation: None,
+            lang,
+            country,
+            markup: Markup::new_source(source),
+        }
+    }
+
+    static LOCALIZED_PAGE: MarkupType = MarkupType {
+        id: 1,
+        name: "LocalizedPage",
+    };
+
+    #[derive(Clone)]
+    struct LocalizedPage {}
+    impl MarkupIdentifier for LocalizedPage {
+        fn get_markup_identity(&self) -> &MarkupType {
+            &LOCALIZED_PAGE
+        }
+    }
+    impl MarkupResourceLocationUtil for LocalizedPage {
+        fn get_component_path(&self) -> &'static str {
+            module_path!()
+        }
+        fn get_component_name(&self) -> &'static str {
+            "LocalizedPage"
+        }
+        fn get_markup_type(&self) -> &'static str {
+            wicket_util::constants::file_ext::HTML
+        }
+    }
+    impl MarkupLookup for LocalizedPage {
+        fn markup_resources(&self) -> &[MarkupResource] {
+            &LOCALIZED_MARKUP
+        }
+    }
+    impl MarkupContainer for LocalizedPage {
+        fn render_component(
+            &self,
+            _id: ComponentId,
+            _response: &mut Response,
+        ) -> std::io::Result<RedirectAction> {
+            Ok(RedirectAction::None)
+        }
+    }
+    impl WebPage for LocalizedPage {}
+
+    #[test]
+    pub fn markup_dimensions_test() {
+        let dimensions = Box::leak(Box::new(ValidHtmlDimensions {
+            style: Some(vec!["dark".to_string()]),
+            variation: None,
+            lang: Some(vec!["fr".to_string(), "en".to_string()]),
+            country: Some(vec!["CA".to_string()]),
+        }));
+        let app = Arc::new(WebApplication {
+            html_dimensions: dimensions,
+            ..Default::default()
+        });
+        let render = |accept_language: &str, session: Option<(Option<Locale>, Option<&str>)>| {
+            let builder =
+                http::Request::builder().header(http::header::ACCEPT_LANGUAGE, accept_language);
+            let mut cycle = app.create_request_cycle(request_with(builder, None));
+            if let Some((locale, style)) = session {
+                let session = cycle.get_or_create_session();
+                session.set_locale(locale);
+                session.set_style(style);
+            }
+            let dimensions = cycle.get_markup_dimensions();
+            render_page(&LocalizedPage {}, dimensions, &mut cycle.response).unwrap();
+            match cycle.response.get_body() {
+                ResponseBody::Buffered(body) => String::from_utf8(body.clone()).unwrap(),
+                _ => panic!("Page is buffered"),
+            }
+        };
+
+        assert_eq!("fr_CA", render("fr-CA,en;q=0.8", None));
+        assert_eq!("fr", render("de-DE,fr-FR;q=0.9", None));
+        assert_eq!("default", render("de", None));
+        // No markup for en, the default is rendered.
+        assert_eq!("default", render("en-GB", None));
+        assert_eq!(
+            "fr",
+            render("en", Some((Some(Locale::new("fr", None)), None)))
+        );
+        assert_eq!("dark", render("de", Some((None, Some("dark")))));
+        // The style takes precedence over the locale, missing translations fall back.
+        assert_eq!("dark", render("en-CA", Some((None, Some("dark")))));
+        assert_eq!("dark_fr", render("fr-CA", Some((None, Some("dark")))));
+        assert_eq!(
+            "fr_CA",
+            render("de", Some((Locale::parse("fr_CA"), Some("light"))))
+        );
+    }
+
+    #[test]
+    pub fn dirty_page_handle_test() {
+        let app = Arc::new(WebApplication::default());
+        let mut cycle = app.create_request_cycle(request("/", None));
+        let page_id = cycle
+            .get_or_create_session()
+            .add_page(Box::new(ProductPage { count: 0 }))
+            .unwrap();
+        let provider = PageProvider::for_instance(page_id, None);
+        let mut change = |version, mutate: bool| {
+            let session = cycle.get_or_create_session();
+            let mut handle = provider.get_page_handle(session).unwrap().unwrap();
+            if mutate {
+                handle.to_mut();
+            }
+            let handle = handle.detach();
+            cycle.set_page_handle(page_id, version, handle);
+            cycle.store_dirty_pages().unwrap();
+            cycle.get_or_create_session().get_latest_version(page_id)
+        };
+
+        // An unchanged page is not stored.
+        assert_eq!(Some(0), change(0, false));
+        assert_eq!(Some(1), change(0, true));
+        assert_eq!(Some(2), change(1, true));
+        // A change to version 0 replaces the newer versions.
+        assert_eq!(Some(1), change(0, true));
+        let session = cycle.get_or_create_session();
+        assert!(session.get_page(page_id, 2).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn page_expired_test() {
+        let app = |page_settings: PageSettings| {
+            product_app(WebApplication {
+                page_settings,
+                ..Default::default()
+            })
+        };
+        let recreate = app(PageSettings::default());
+        let session_id = recreate.sessions.new_session();
+        let process = |uri| recreate.process_request(request(uri, Some(session_id)));
+
+        // The mounted page of the expired instance is recreated as a new instance.
+        let response = process("/product/5?3").await.unwrap();
+        assert_eq!(200, response.status);
+        // The listener of the expired instance is not invoked.
+        let response = process("/product/5?3-0.click-form-link").await.unwrap();
+        assert_eq!(200, response.status);
+        {
+            let handle = recreate.sessions.get_session_handle(session_id).unwrap();
+            let mut session = handle.lock().await;
+            assert!(session.get_page(1, 0).unwrap().is_some());
+            assert!(session.get_page(3, 0).unwrap().is_none());
+        }
+        // A page instance url cannot be recreated.
+        let response = process("/wicket/page?3").await.unwrap();
+        assert_eq!(410, response.status);
+        // Without a session the page is recreated in a new one.
+        let response = recreate
+            .process_request(request("/product/5?3", None))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert!(response.get_header("Set-Cookie").is_some());
+
+        let no_recreate = app(PageSettings {
+            recreate_bookmarkable_pages_after_expiry: false,
+            ..Default::default()
+        });
+        let response = no_recreate
+            .process_request(request("/product/5?3-0.click-form-link", None))
+            .await
+            .unwrap();
+        assert_eq!(410, response.status);
+
+        let error_page = app(PageSettings {
+            recreate_bookmarkable_pages_after_expiry: false,
+            page_expired_error_page: Some(ProductPage::markup_type()),
+        });
+        let response = error_page
+            .process_request(request("/wicket/page?3", None))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(
+            Some("text/html; charset=utf-8"),
+            response.get_content_type()
+        );
+    }
+
+    #[tokio::test]
+    pub async fn redirect_test() {
+        let app = product_app(WebApplication {
+            redirect_settings: RedirectSettings {
+                allowed_hosts: vec!["example.com".to_string()],
+            },
+            ..Default::default()
+        });
+        let session_id = app.sessions.new_session();
+        let process = |method: &str, uri: &str, ajax: bool| {
+            let mut builder = http::Request::builder().method(method).uri(uri);
+            if ajax {
+                builder = builder.header("Wicket-Ajax", "true");
+            }
+            app.process_request(request_with(builder, Some(session_id)))
+        };
+
+        process("GET", "/product/5", false).await.unwrap();
+
+        // Post, redirect to the new version, get.
+        let response = process("POST", "/product/5?0-0.submit-form-save", false)
+            .await
+            .unwrap();
+        assert_eq!(303, response.status);
+        assert_eq!(Some("/product/5?0"), response.get_header("Location"));
+        let response = process("GET", "/product/5?0.submit-form-save", true)
+            .await
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(Some("/product/5?0"), response.get_header("Ajax-Location"));
+        assert_eq!(None, response.get_header("Location"));
+        {
+            let handle = app.sessions.get_session_handle(session_id).unwrap();
+            let session = handle.lock().await;
+            assert_eq!(Some(2), session.get_latest_version(0));
+        }
+
+        // The new page is stored as instance 1.
+        let response = process("GET", "/product/5?0.click-form-next", false)
+            .await
+            .unwrap();
+        assert_eq!(302, response.status);
+        let location = response.get_header("Location").unwrap().to_string();
+        assert!(location.ends_with("?1"), "{}", location);
+        let response = process("GET", &location, false).await.unwrap();
+        assert_eq!(200, response.status);
+
+        let response = process(
+            "GET",
+            "/product/5?0.click-form-external&to=https%3A%2F%2Fexample.com%2Fdone",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(302, response.status);
+        assert_eq!(
+            Some("https://example.com/done"),
+            response.get_header("Location")
+        );
+        let response = process(
+            "GET",
+            "/product/5?0.click-form-external&to=https%3A%2F%2Fevil.com",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(403, response.status);
+        assert_eq!(None, response.get_header("Location"));
+    }
+
+    #[tokio::test]
+    pub async fn render_strategy_test() {
+        let app = |render_strategy: RenderStrategy| {
+            product_app(WebApplication {
+                render_strategy,
+                ..Default::default()
+            })
+        };
+        let buffer = app(RenderStrategy::RedirectToBuffer);
+        let session_id = buffer.sessions.new_session();
+        let process = |uri: &str| buffer.process_request(request(uri, Some(session_id)));
+        process("/product/5").await.unwrap();
+
+        // The listener renders into the session and redirects to the page url.
+        let response = process("/product/5?0-0.click-form-link").await.unwrap();
+        assert_eq!(302, response.status);
+        assert_eq!(Some("/product/5?0"), response.get_header("Location"));
+        {
+            let handle = buffer.sessions.get_session_handle(session_id).unwrap();
+            let mut session = handle.lock().await;
+            let buffered = session.take_buffered_response("/product/5?0").unwrap();
+            assert_eq!(PRODUCT_HTML, body(buffered));
+
+            let mut buffered = Response::new();
+            buffered.set_body(ResponseBody::Buffered(b"buffered".to_vec()));
+            session.buffer_response("/product/5?0".to_string(), buffered);
+        }
+        // The buffer is served once, instead of rendering the page.
+        let response = process("/product/5?0").await.unwrap();
+        assert_eq!("buffered", body(response));
+        let response = process("/product/5?0").await.unwrap();
+        assert_eq!(PRODUCT_HTML, body(response));
+
+        // The buffer is served even when the url no longer resolves the page instance or
+        // a handler.
+        for (url, status) in [("/wicket/page?9", 410), ("/no/such/page", 404)] {
+            {
+                let handle = buffer.sessions.get_session_handle(session_id).unwrap();
+                let mut session = handle.lock().await;
+                let mut buffered = Response::new();
+                buffered.set_body(ResponseBody::Buffered(b"buffered".to_vec()));
+                session.buffer_response(url.to_string(), buffered);
+            }
+            let response = process(url).await.unwrap();
+            assert_eq!(200, response.status);
+            assert_eq!("buffered", body(response));
+            let response = process(url).await.unwrap();
+            assert_eq!(status, response.status);
+        }
+
+        for (strategy, status) in [
+            (RenderStrategy::OnePassRender, 200),
+            (RenderStrategy::RedirectToRender, 302),
+        ] {
+            let app = app(strategy);
+            let session_id = app.sessions.new_session();
+            let process = |uri: &str| app.process_request(request(uri, Some(session_id)));
+            process("/product/5").await.unwrap();
+            let response = process("/product/5?0-0.click-form-link").await.unwrap();
+            assert_eq!(status, response.status);
+
+            let handle = app.sessions.get_session_handle(session_id).unwrap();
+            let mut session = handle.lock().await;
+            assert!(session.take_buffered_response("/product/5?0").is_none());
+        }
     }
 }
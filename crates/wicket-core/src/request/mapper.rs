@@ -1,48 +1,994 @@
-use crate::request::{RequestMapper, RequestMapperLogic};
+pub mod crypto;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use wicket_request::request::mapper::info::PageComponentInfo;
+use wicket_request::request::mapper::parameter::{NamedPair, PageParameters, ValueType};
+use wicket_request::request::url::Url;
+use wicket_util::constants::file_ext;
+
+use crate::components::MarkupType;
+use crate::core::util::resource::locator::ResourceStreamLocator;
+use crate::request::handler::{
+    ListenerRequestHandler, PageProvider, RenderPageRequestHandler, ResourceStreamRequestHandler,
+};
+use crate::request::{RequestHandler, RequestMapper, RequestMapperLogic, RequestMappingResult};
+use crate::session::page_factory::{find_page_entry, get_page_entry, PageEntry};
 
 /// Replace java SystemMapper.
+/// Mounted mappers are added with WebApplication::mount_page.
 pub fn get_default_mappers() -> Vec<RequestMapper> {
-    vec![RequestMapper::Mounted(MountedMapper::default())]
+    vec![
+        RequestMapper::PageInstance(PageInstanceMapper {}),
+        RequestMapper::Bookmarkable(BookmarkableMapper::default()),
+    ]
+}
+
+/// The first segment of the urls reserved by the framework mappers.
+const WICKET_SEGMENT: &str = "wicket";
+const BOOKMARKABLE_SEGMENT: &str = "bookmarkable";
+const PAGE_SEGMENT: &str = "page";
+
+/// A segment of a mount path template.
+#[derive(Debug, PartialEq, Eq)]
+enum MountSegment {
+    /// Must equal the url segment.
+    Fixed(String),
+    /// ${name} a required page parameter.
+    Required(String),
+    /// #{name} an optional page parameter.
+    Optional(String),
+}
+
+impl MountSegment {
+    fn parse(segment: &str) -> Self {
+        let placeholder = |prefix: &str| {
+            segment
+                .strip_prefix(prefix)
+                .and_then(|s| s.strip_suffix('}'))
+                .map(|name| {
+                    if name.is_empty() {
+                        panic!("Error: empty parameter name in mount segment '{}'", segment);
+                    }
+                    name.to_string()
+                })
+        };
+        if let Some(name) = placeholder("${") {
+            MountSegment::Required(name)
+        } else if let Some(name) = placeholder("#{") {
+            MountSegment::Optional(name)
+        } else {
+            MountSegment::Fixed(segment.to_string())
+        }
+    }
+}
+
+// Compatibility score weights, a fixed segment match is more specific than a placeholder.
+const FIXED_SEGMENT_SCORE: i32 = 2;
+const PARAMETER_SEGMENT_SCORE: i32 = 1;
+
+/// Map a page type to a path template eg "/product/${id}/#{tab}".
+///
+/// Required `${}` and optional `#{}` segments are passed to the page as
+/// PageParameters of ValueType::Path, the query string as ValueType::QueryString.
+/// Optional parameters are filled left to right from the surplus url segments.
+pub struct MountedMapper {
+    segments: Vec<MountSegment>,
+    page_type: &'static MarkupType,
+}
+
+impl MountedMapper {
+    /// Map the path template eg "/product/${id}/#{tab}" to the page type.
+    pub fn new(path: &str, page_type: &'static MarkupType) -> Self {
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(MountSegment::parse)
+            .collect();
+        Self {
+            segments,
+            page_type,
+        }
+    }
+
+    /// Match the url segments against the template, return the path parameters and score.
+    fn match_segments(&self, url_segments: &[String]) -> Option<(Vec<NamedPair>, i32)> {
+        let optional_count = self
+            .segments
+            .iter()
+            .filter(|s| matches!(s, MountSegment::Optional(_)))
+            .count();
+        let required_count = self.segments.len() - optional_count;
+        if url_segments.len() < required_count
+            || url_segments.len() > required_count + optional_count
+        {
+            return None;
+        }
+
+        let mut optional_available = url_segments.len() - required_count;
+        let mut url_iter = url_segments.iter();
+        let mut params = Vec::new();
+        // Any match beats no match.
+        let mut score = 1;
+        for segment in &self.segments {
+            match segment {
+                MountSegment::Fixed(fixed) => {
+                    if url_iter.next()? != fixed {
+                        return None;
+                    }
+                    score += FIXED_SEGMENT_SCORE;
+                }
+                MountSegment::Required(name) => {
+                    params.push(path_pair(name, url_iter.next()?));
+                    score += PARAMETER_SEGMENT_SCORE;
+                }
+                MountSegment::Optional(name) => {
+                    if optional_available > 0 {
+                        optional_available -= 1;
+                        params.push(path_pair(name, url_iter.next()?));
+                        score += PARAMETER_SEGMENT_SCORE;
+                    }
+                }
+            }
+        }
+        Some((params, score))
+    }
+}
+
+fn path_pair(key: &str, value: &str) -> NamedPair {
+    NamedPair {
+        key: key.to_string(),
+        value: value.to_string(),
+        value_type: ValueType::Path,
+    }
+}
+
+/// The page parameters of a bookmarkable or package url, the segments following the
+/// page name are indexed parameters.
+fn page_parameters(indexed: &[String], url: &Url) -> Option<PageParameters> {
+    let mut params = PageParameters::from_url(url);
+    params.indexed_parameters = indexed.to_vec();
+    (!params.is_empty()).then_some(params)
+}
+
+/// The handler for a page url, a listener invocation when the page info names a
+/// component of the page instance.
+fn page_request_handler(
+    mut provider: PageProvider,
+    info: Option<PageComponentInfo>,
+) -> Box<dyn RequestHandler> {
+    let Some(info) = info else {
+        return Box::new(RenderPageRequestHandler::new(provider));
+    };
+    provider.page_id = Some(info.page_id);
+    provider.render_id = info.render_count;
+    match info.component_info {
+        Some(component_info) => Box::new(ListenerRequestHandler::new(provider, component_info)),
+        None => Box::new(RenderPageRequestHandler::new(provider)),
+    }
+}
+
+/// Add the page info of a page instance handler to the url.
+fn insert_page_info(url: &mut Url, handler: &dyn RequestHandler, provider: &PageProvider) {
+    if let Some(page_id) = provider.page_id {
+        let info = PageComponentInfo {
+            page_id,
+            render_count: provider.render_id,
+            component_info: handler.get_component_info().cloned(),
+        };
+        info.insert_into(url);
+    }
 }
 
-#[derive(Default)]
-pub struct MountedMapper {}
 impl RequestMapperLogic for MountedMapper {
-    fn map_request(&self, _request: &super::Request) -> Option<super::RequestMappingResult> {
-        todo!()
+    fn map_request(&self, request: &super::Request) -> Option<RequestMappingResult> {
+        let mut url = request.get_url();
+        let (path_params, score) = self.match_segments(&url.segments)?;
+        let info = PageComponentInfo::take_from_url(&mut url);
+
+        let mut params = PageParameters::new();
+        params.named_parameters = path_params;
+        params.add_query_parameters(&url);
+
+        let provider = PageProvider::new(self.page_type, Some(params));
+        Some(RequestMappingResult {
+            handler: page_request_handler(provider, info),
+            compatibility_score: score,
+        })
+    }
+
+    fn map_handler(&self, handler: &dyn super::RequestHandler) -> Option<Url> {
+        let provider = handler.as_page_provider().as_ref()?;
+        if provider.page_type?.id != self.page_type.id {
+            return None;
+        }
+        let params = provider.params.as_ref();
+        // The path template has no place for indexed parameters.
+        if params.is_some_and(|p| !p.indexed_parameters.is_empty()) {
+            return None;
+        }
+        let get_param = |name: &str| params.and_then(|p| p.get(name)).map(|np| &np.value);
+
+        let mut url = Url::new();
+        let mut used: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                MountSegment::Fixed(fixed) => url.segments.push(fixed.clone()),
+                MountSegment::Required(name) => {
+                    url.segments.push(get_param(name)?.clone());
+                    used.push(name);
+                }
+                MountSegment::Optional(name) => {
+                    if let Some(value) = get_param(name) {
+                        url.segments.push(value.clone());
+                        used.push(name);
+                    }
+                }
+            }
+        }
+
+        // Parameters not consumed by the path template go to the query string.
+        for param in params.iter().flat_map(|p| &p.named_parameters) {
+            if !used
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&param.key))
+            {
+                url = url.add_query_parameter(param.key.as_str(), param.value.as_str());
+            }
+        }
+        insert_page_info(&mut url, handler, provider);
+        Some(url)
     }
+}
+
+/// Address a page instance stored in the session by its page info alone
+/// eg "/wicket/page?3-1", the fallback for pages without a mount or bookmarkable url.
+pub struct PageInstanceMapper {}
+
+impl PageInstanceMapper {
+    /// Scored as if mounted at "/wicket/page".
+    fn compatibility_score() -> i32 {
+        1 + 2 * FIXED_SEGMENT_SCORE
+    }
+}
+
+impl RequestMapperLogic for PageInstanceMapper {
+    fn map_request(&self, request: &super::Request) -> Option<RequestMappingResult> {
+        let mut url = request.get_url();
+        let [wicket, page] = url.segments.as_slice() else {
+            return None;
+        };
+        if wicket != WICKET_SEGMENT || page != PAGE_SEGMENT {
+            return None;
+        }
+        let info = PageComponentInfo::take_from_url(&mut url)?;
+
+        let provider = PageProvider::for_instance(info.page_id, info.render_count);
+        Some(RequestMappingResult {
+            handler: page_request_handler(provider, Some(info)),
+            compatibility_score: Self::compatibility_score(),
+        })
+    }
+
+    fn map_handler(&self, handler: &dyn super::RequestHandler) -> Option<Url> {
+        let provider = handler.as_page_provider().as_ref()?;
+        provider.page_id?;
+        let mut url = Url::new()
+            .add_segment(WICKET_SEGMENT)
+            .add_segment(PAGE_SEGMENT);
+        insert_page_info(&mut url, handler, provider);
+        Some(url)
+    }
+}
 
-    fn map_handler(&self, _handler: &dyn super::RequestHandler) -> Option<url::Url> {
-        todo!()
+/// Serve every page registered in a rust module below a url prefix,
+/// `PackageMapper::new("/admin", "myapp::admin")` maps "/admin/UserPage" to
+/// myapp::admin::UserPage. Pages of sub modules are not included.
+///
+/// The query string is passed to the page as PageParameters of ValueType::QueryString,
+/// segments after the page name eg "/admin/UserPage/42" as indexed parameters.
+pub struct PackageMapper {
+    prefix: Vec<String>,
+    module_path: String,
+}
+
+impl PackageMapper {
+    /// Map `<prefix>/<PageName>` to the pages registered in the rust module path.
+    pub fn new(prefix: &str, module_path: &str) -> Self {
+        let prefix = prefix
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self {
+            prefix,
+            module_path: module_path.to_string(),
+        }
+    }
+
+    fn contains(&self, entry: &PageEntry) -> bool {
+        entry.module_path == self.module_path
+    }
+
+    /// Scored as if mounted at "<prefix>/${page}".
+    fn compatibility_score(&self) -> i32 {
+        1 + self.prefix.len() as i32 * FIXED_SEGMENT_SCORE + PARAMETER_SEGMENT_SCORE
     }
 }
-pub struct PackageMapper {}
+
 impl RequestMapperLogic for PackageMapper {
-    fn map_request(&self, _request: &super::Request) -> Option<super::RequestMappingResult> {
-        todo!()
+    fn map_request(&self, request: &super::Request) -> Option<RequestMappingResult> {
+        let mut url = request.get_url();
+        let [page_name, indexed @ ..] = url.segments.strip_prefix(self.prefix.as_slice())? else {
+            return None;
+        };
+        let entry = find_page_entry(page_name).filter(|entry| self.contains(entry))?;
+        let indexed = indexed.to_vec();
+        let info = PageComponentInfo::take_from_url(&mut url);
+        let params = page_parameters(&indexed, &url);
+
+        let provider = PageProvider::new(entry.id, params);
+        Some(RequestMappingResult {
+            handler: page_request_handler(provider, info),
+            compatibility_score: self.compatibility_score(),
+        })
+    }
+
+    fn map_handler(&self, handler: &dyn super::RequestHandler) -> Option<Url> {
+        let provider = handler.as_page_provider().as_ref()?;
+        let entry = get_page_entry(provider.page_type?.id).filter(|entry| self.contains(entry))?;
+
+        let mut url = Url::new();
+        url.segments.extend(self.prefix.iter().cloned());
+        url = url.add_segment(entry.id.name);
+        if let Some(params) = &provider.params {
+            params.encode_into(&mut url);
+        }
+        insert_page_info(&mut url, handler, provider);
+        Some(url)
+    }
+}
+
+/// The Cache-Control policy of the resources served by a ResourceMapper.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CacheControl {
+    /// Revalidate on every use, answered with a 304 while the resource is unchanged.
+    #[default]
+    NoCache,
+    /// Never store, eg private downloads.
+    NoStore,
+    /// Use without revalidation for the number of seconds.
+    MaxAge(u32),
+    /// MaxAge for resources that never change under their url eg versioned file names.
+    Immutable(u32),
+}
+
+impl CacheControl {
+    /// The Cache-Control header value of the policy.
+    pub fn header_value(&self) -> String {
+        match self {
+            CacheControl::NoCache => "no-cache".to_string(),
+            CacheControl::NoStore => "no-store".to_string(),
+            CacheControl::MaxAge(secs) => format!("public, max-age={}", secs),
+            CacheControl::Immutable(secs) => format!("public, max-age={}, immutable", secs),
+        }
+    }
+}
+
+/// Serve static resources eg css, js and images below a url prefix, "/static/css/site.css"
+/// is located as "css/site.css" by the ResourceStreamLocator.
+///
+/// The content type follows the file extension. Responses carry a strong ETag and
+/// Last-Modified and conditional GETs are answered with 304. Segments starting with '.'
+/// are refused, so neither "../" nor hidden files can be reached. The file is opened
+/// when the handler responds, a missing file is answered with 404.
+pub struct ResourceMapper {
+    prefix: Vec<String>,
+    locator: Arc<dyn ResourceStreamLocator>,
+    cache_control: CacheControl,
+}
+
+impl ResourceMapper {
+    /// Serve the resources of the locator below the url prefix, revalidated on each use.
+    pub fn new(prefix: &str, locator: Box<dyn ResourceStreamLocator>) -> Self {
+        let prefix = prefix
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self {
+            prefix,
+            locator: Arc::from(locator),
+            cache_control: CacheControl::default(),
+        }
+    }
+
+    /// Set the Cache-Control of the resources served by the mapper.
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+
+    /// The url of the resource at the relative path eg "css/site.css".
+    pub fn url_for(&self, path: &str) -> Url {
+        let segments = path.split('/').filter(|s| !s.is_empty());
+        Url {
+            segments: self
+                .prefix
+                .iter()
+                .map(String::as_str)
+                .chain(segments)
+                .map(str::to_string)
+                .collect(),
+            query_parameters: Vec::new(),
+        }
     }
 
-    fn map_handler(&self, _handler: &dyn super::RequestHandler) -> Option<url::Url> {
-        todo!()
+    /// The relative resource path of the url, None when outside the prefix or unsafe.
+    fn resource_path(&self, url_segments: &[String]) -> Option<PathBuf> {
+        let segments = url_segments.strip_prefix(self.prefix.as_slice())?;
+        if segments.is_empty() {
+            return None;
+        }
+        let mut path = PathBuf::new();
+        for segment in segments {
+            // Decoded segments may hold a separator eg "..%2F".
+            if segment.starts_with('.') || segment.contains(['/', '\\', ':', '\0']) {
+                return None;
+            }
+            path.push(segment);
+        }
+        Some(path)
     }
 }
-pub struct ResourceMapper {}
+
 impl RequestMapperLogic for ResourceMapper {
-    fn map_request(&self, _request: &super::Request) -> Option<super::RequestMappingResult> {
-        todo!()
+    fn map_request(&self, request: &super::Request) -> Option<RequestMappingResult> {
+        let url = request.get_url();
+        // The file is opened by the handler, map_request does not block.
+        let path = self.resource_path(&url.segments)?;
+
+        let content_type = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(file_ext::content_type)
+            .unwrap_or("application/octet-stream");
+        let handler = ResourceStreamRequestHandler::new(
+            self.locator.clone(),
+            path,
+            Some(content_type.to_string()),
+        )
+        .with_cache_control(self.cache_control.header_value());
+
+        let fixed = self.prefix.len() as i32;
+        let parameters = (url.segments.len() - self.prefix.len()) as i32;
+        Some(RequestMappingResult {
+            handler: Box::new(handler),
+            compatibility_score: 1
+                + fixed * FIXED_SEGMENT_SCORE
+                + parameters * PARAMETER_SEGMENT_SCORE,
+        })
+    }
+
+    /// Resources are not addressed through handlers, see [ResourceMapper::url_for].
+    fn map_handler(&self, _handler: &dyn super::RequestHandler) -> Option<Url> {
+        None
+    }
+}
+
+/// Serve any page registered with `#[wicket_page]` at
+/// "/wicket/bookmarkable/<PageName>?params" without an explicit mount.
+///
+/// The query string is passed to the page as PageParameters of ValueType::QueryString,
+/// segments after the page name as indexed parameters.
+/// Restrict the reachable pages with [BookmarkableMapper::with_allowed_pages].
+#[derive(Default)]
+pub struct BookmarkableMapper {
+    // None allows every registered page.
+    allowed_pages: Option<Vec<u16>>,
+}
+
+impl BookmarkableMapper {
+    /// Only the listed page types are reachable by name, keeping internal pages private.
+    pub fn with_allowed_pages(page_types: &[&'static MarkupType]) -> Self {
+        Self {
+            allowed_pages: Some(page_types.iter().map(|pt| pt.id).collect()),
+        }
     }
 
-    fn map_handler(&self, _handler: &dyn super::RequestHandler) -> Option<url::Url> {
-        todo!()
+    fn is_allowed(&self, page_type: &MarkupType) -> bool {
+        self.allowed_pages
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&page_type.id))
+    }
+
+    /// Scored as if mounted at "/wicket/bookmarkable/${page}", an explicit mount
+    /// of the same specificity added later wins.
+    fn compatibility_score() -> i32 {
+        1 + 2 * FIXED_SEGMENT_SCORE + PARAMETER_SEGMENT_SCORE
     }
 }
-pub struct BookmarkableMapper {}
+
 impl RequestMapperLogic for BookmarkableMapper {
-    fn map_request(&self, _request: &super::Request) -> Option<super::RequestMappingResult> {
-        todo!()
+    fn map_request(&self, request: &super::Request) -> Option<RequestMappingResult> {
+        let mut url = request.get_url();
+        let [wicket, bookmarkable, page_name, indexed @ ..] = url.segments.as_slice() else {
+            return None;
+        };
+        if wicket != WICKET_SEGMENT || bookmarkable != BOOKMARKABLE_SEGMENT {
+            return None;
+        }
+        let entry = find_page_entry(page_name)?;
+        if !self.is_allowed(entry.id) {
+            return None;
+        }
+        let indexed = indexed.to_vec();
+        let info = PageComponentInfo::take_from_url(&mut url);
+        let params = page_parameters(&indexed, &url);
+
+        let provider = PageProvider::new(entry.id, params);
+        Some(RequestMappingResult {
+            handler: page_request_handler(provider, info),
+            compatibility_score: Self::compatibility_score(),
+        })
+    }
+
+    fn map_handler(&self, handler: &dyn super::RequestHandler) -> Option<Url> {
+        let provider = handler.as_page_provider().as_ref()?;
+        let page_type = provider.page_type?;
+        if !self.is_allowed(page_type) {
+            return None;
+        }
+        // Only pages constructable by name can be reached.
+        find_page_entry(page_type.name)?;
+
+        let mut url = Url::new()
+            .add_segment(WICKET_SEGMENT)
+            .add_segment(BOOKMARKABLE_SEGMENT)
+            .add_segment(page_type.name);
+        if let Some(params) = &provider.params {
+            params.encode_into(&mut url);
+        }
+        insert_page_info(&mut url, handler, provider);
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use wicket_request::request::mapper::info::ComponentInfo;
+    use wicket_request::request::mapper::parameter::{PageParameters, ValueType};
+    use wicket_request::request::url::Url;
+
+    use crate::components::StaticMarkupIdentifier;
+    use crate::core::util::resource::locator::FileResourceStreamLocator;
+    use crate::protocol::http::WebApplication;
+    use crate::request::handler::{ListenerRequestHandler, PageProvider, RenderPageRequestHandler};
+    use crate::request::{RequestMapperLogic, ResponseBody};
+    use crate::test_util::{body, product_app, request, ProductPage, PRODUCT_HTML};
+
+    use super::{
+        BookmarkableMapper, CacheControl, MountedMapper, PackageMapper, PageInstanceMapper,
+        ResourceMapper,
+    };
+
+    fn param<'a>(params: &'a PageParameters, key: &str) -> Option<(&'a str, ValueType)> {
+        params.get(key).map(|np| (np.value.as_str(), np.value_type))
+    }
+
+    #[test]
+    pub fn map_request_test() {
+        let mapper = MountedMapper::new("/product/${id}/#{tab}", ProductPage::markup_type());
+
+        let result = mapper
+            .map_request(&request("/product/5/specs?ref=home", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(Some(("5", ValueType::Path)), param(params, "id"));
+        assert_eq!(Some(("specs", ValueType::Path)), param(params, "tab"));
+        assert_eq!(Some(("home", ValueType::QueryString)), param(params, "ref"));
+
+        let result = mapper
+            .map_request(&request("/product/a%20b", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(Some(("a b", ValueType::Path)), param(params, "id"));
+        assert!(params.get("tab").is_none());
+
+        assert!(mapper.map_request(&request("/product", None)).is_none());
+        assert!(mapper
+            .map_request(&request("/product/5/specs/x", None))
+            .is_none());
+        assert!(mapper.map_request(&request("/other/5", None)).is_none());
+    }
+
+    #[test]
+    pub fn compatibility_score_test() {
+        let page_type = ProductPage::markup_type();
+        let score = |template: &str, uri: &str| {
+            MountedMapper::new(template, page_type)
+                .map_request(&request(uri, None))
+                .map(|r| r.compatibility_score)
+        };
+        assert!(score("/product/new", "/product/new") > score("/product/${id}", "/product/new"));
+        assert!(score("/product/${id}", "/product/new") > score("/${kind}/${id}", "/product/new"));
+        assert!(score("/", "/").unwrap() > 0);
+        assert_eq!(
+            score("/product/#{id}", "/product/1").unwrap(),
+            score("/product/${id}", "/product/1").unwrap()
+        );
+    }
+
+    #[test]
+    pub fn map_handler_test() {
+        let mapper = MountedMapper::new("/product/${id}/#{tab}", ProductPage::markup_type());
+        let params = PageParameters::new()
+            .add("id".to_string(), "a/b".to_string())
+            .add("ref".to_string(), "home".to_string());
+        let handler = RenderPageRequestHandler::new(PageProvider::new(
+            ProductPage::markup_type(),
+            Some(params),
+        ));
+        let url = mapper.map_handler(&handler).unwrap();
+        assert_eq!("/product/a%2Fb?ref=home", url.to_string());
+
+        // The round trip.
+        let result = mapper
+            .map_request(&request(&url.to_string(), None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert_eq!(
+            "a/b",
+            provider.params.as_ref().unwrap().get("id").unwrap().value
+        );
+
+        // A required parameter is missing.
+        let handler =
+            RenderPageRequestHandler::new(PageProvider::new(ProductPage::markup_type(), None));
+        assert_eq!(
+            None,
+            mapper.map_handler(&handler).map(|u: Url| u.to_string())
+        );
+
+        // Indexed parameters have no place in the path template.
+        let handler = RenderPageRequestHandler::new(PageProvider::new(
+            ProductPage::markup_type(),
+            Some(
+                PageParameters::new()
+                    .add("id".to_string(), "5".to_string())
+                    .add_indexed("x".to_string()),
+            ),
+        ));
+        assert!(mapper.map_handler(&handler).is_none());
+    }
+
+    #[test]
+    pub fn bookmarkable_map_request_test() {
+        let mapper = BookmarkableMapper::default();
+        let result = mapper
+            .map_request(&request("/wicket/bookmarkable/ProductPage?id=3", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert_eq!(
+            ProductPage::markup_type().id,
+            provider.page_type.unwrap().id
+        );
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(Some(("3", ValueType::QueryString)), param(params, "id"));
+
+        assert!(mapper
+            .map_request(&request("/wicket/bookmarkable/NoSuchPage", None))
+            .is_none());
+        assert!(mapper
+            .map_request(&request("/product/ProductPage", None))
+            .is_none());
+
+        // Outranked by an explicit mount of the same url.
+        let mounted = MountedMapper::new(
+            "/wicket/bookmarkable/ProductPage",
+            ProductPage::markup_type(),
+        )
+        .map_request(&request("/wicket/bookmarkable/ProductPage", None))
+        .unwrap();
+        assert!(mounted.compatibility_score > result.compatibility_score);
+    }
+
+    #[test]
+    pub fn bookmarkable_allowed_pages_test() {
+        let mapper = BookmarkableMapper::with_allowed_pages(&[]);
+        assert!(mapper
+            .map_request(&request("/wicket/bookmarkable/ProductPage", None))
+            .is_none());
+        let handler =
+            RenderPageRequestHandler::new(PageProvider::new(ProductPage::markup_type(), None));
+        assert!(mapper.map_handler(&handler).is_none());
+
+        let mapper = BookmarkableMapper::with_allowed_pages(&[ProductPage::markup_type()]);
+        assert!(mapper
+            .map_request(&request("/wicket/bookmarkable/ProductPage", None))
+            .is_some());
+    }
+
+    #[test]
+    pub fn bookmarkable_map_handler_test() {
+        let mapper = BookmarkableMapper::default();
+        let handler = RenderPageRequestHandler::new(PageProvider::new(
+            ProductPage::markup_type(),
+            Some(PageParameters::new().add("q".to_string(), "a b".to_string())),
+        ));
+        let url = mapper.map_handler(&handler).unwrap();
+        assert_eq!("/wicket/bookmarkable/ProductPage?q=a+b", url.to_string());
+
+        let result = mapper
+            .map_request(&request(&url.to_string(), None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert_eq!(
+            "a b",
+            provider.params.as_ref().unwrap().get("q").unwrap().value
+        );
+
+        let handler = RenderPageRequestHandler::new(PageProvider::new(
+            ProductPage::markup_type(),
+            Some(
+                PageParameters::new()
+                    .add_indexed("2024".to_string())
+                    .add_indexed("a/b".to_string())
+                    .add("tag".to_string(), "x".to_string())
+                    .add("tag".to_string(), "y".to_string()),
+            ),
+        ));
+        let url = mapper.map_handler(&handler).unwrap();
+        assert_eq!(
+            "/wicket/bookmarkable/ProductPage/2024/a%2Fb?tag=x&tag=y",
+            url.to_string()
+        );
+        let result = mapper
+            .map_request(&request(&url.to_string(), None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(vec!["2024", "a/b"], params.indexed_parameters);
+        assert_eq!(vec!["x", "y"], params.get_all("tag").collect::<Vec<_>>());
+    }
+
+    #[test]
+    pub fn page_instance_mapper_test() {
+        let mapper = PageInstanceMapper {};
+        let result = mapper
+            .map_request(&request("/wicket/page?3-1", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert!(provider.page_type.is_none());
+        assert_eq!((Some(3), Some(1)), (provider.page_id, provider.render_id));
+        assert!(result.handler.get_component_info().is_none());
+        assert_eq!(
+            "/wicket/page?3-1",
+            mapper
+                .map_handler(result.handler.as_ref())
+                .unwrap()
+                .to_string()
+        );
+
+        let result = mapper
+            .map_request(&request("/wicket/page?3.click-form-link", None))
+            .unwrap();
+        let info = result.handler.get_component_info().unwrap();
+        assert_eq!(
+            ("click", "form:link"),
+            (info.listener.as_str(), info.component_path.as_str())
+        );
+        assert_eq!(
+            "/wicket/page?3.click-form-link",
+            mapper
+                .map_handler(result.handler.as_ref())
+                .unwrap()
+                .to_string()
+        );
+
+        assert!(mapper.map_request(&request("/wicket/page", None)).is_none());
+        assert!(mapper
+            .map_request(&request("/wicket/page?id=3", None))
+            .is_none());
+        let handler =
+            RenderPageRequestHandler::new(PageProvider::new(ProductPage::markup_type(), None));
+        assert!(mapper.map_handler(&handler).is_none());
+    }
+
+    #[test]
+    pub fn mounted_page_info_test() {
+        let mapper = MountedMapper::new("/product/${id}", ProductPage::markup_type());
+        let result = mapper
+            .map_request(&request("/product/5?ref=home&2-4", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert_eq!((Some(2), Some(4)), (provider.page_id, provider.render_id));
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(2, params.named_parameters.len());
+
+        let mut provider = PageProvider::new(
+            ProductPage::markup_type(),
+            Some(PageParameters::new().add("id".to_string(), "5".to_string())),
+        );
+        provider.page_id = Some(2);
+        provider.render_id = Some(4);
+        let handler = ListenerRequestHandler::new(
+            provider,
+            ComponentInfo {
+                listener: "click".to_string(),
+                component_path: "form:link".to_string(),
+            },
+        );
+        assert_eq!(
+            "/product/5?2-4.click-form-link",
+            mapper.map_handler(&handler).unwrap().to_string()
+        );
+        let result = mapper
+            .map_request(&request("/product/5?2-4.click-form-link", None))
+            .unwrap();
+        assert!(result.handler.get_component_info().is_some());
+    }
+
+    #[test]
+    pub fn package_mapper_test() {
+        let mapper = PackageMapper::new("/admin/pages", "wicket_core::test_util");
+        let result = mapper
+            .map_request(&request("/admin/pages/ProductPage?id=4&1-2", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        assert_eq!(
+            ProductPage::markup_type().id,
+            provider.page_type.unwrap().id
+        );
+        assert_eq!((Some(1), Some(2)), (provider.page_id, provider.render_id));
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(Some(("4", ValueType::QueryString)), param(params, "id"));
+        assert_eq!(
+            "/admin/pages/ProductPage?1-2&id=4",
+            mapper
+                .map_handler(result.handler.as_ref())
+                .unwrap()
+                .to_string()
+        );
+
+        let result = mapper
+            .map_request(&request("/admin/pages/ProductPage/7?tab=specs", None))
+            .unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!(Some("7"), params.get_indexed(0));
+        assert_eq!(
+            "/admin/pages/ProductPage/7?tab=specs",
+            mapper
+                .map_handler(result.handler.as_ref())
+                .unwrap()
+                .to_string()
+        );
+
+        for uri in [
+            "/admin/pages",
+            "/admin/pages/NoSuchPage",
+            "/admin/ProductPage",
+            // Registered in another module.
+            "/admin/pages/TestPage",
+        ] {
+            assert!(mapper.map_request(&request(uri, None)).is_none(), "{}", uri);
+        }
+
+        let mapper = PackageMapper::new("/admin", "wicket_core::other");
+        assert!(mapper
+            .map_request(&request("/admin/ProductPage", None))
+            .is_none());
+        let handler =
+            RenderPageRequestHandler::new(PageProvider::new(ProductPage::markup_type(), None));
+        assert!(mapper.map_handler(&handler).is_none());
+    }
+
+    fn resource_mapper() -> ResourceMapper {
+        let root =
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/resources/static");
+        ResourceMapper::new(
+            "/static",
+            Box::new(FileResourceStreamLocator::new(vec![root])),
+        )
+    }
+
+    #[test]
+    pub fn resource_mapper_test() {
+        let mapper = resource_mapper();
+        assert!(mapper
+            .map_request(&request("/static/css/site.css", None))
+            .is_some());
+        assert_eq!(
+            "/static/css/site.css",
+            mapper.url_for("css/site.css").to_string()
+        );
+
+        for uri in [
+            "/static",
+            "/static/.hidden",
+            "/static/css/../.hidden",
+            "/static/css/..%2F.hidden",
+            "/other/css/site.css",
+        ] {
+            assert!(mapper.map_request(&request(uri, None)).is_none(), "{}", uri);
+        }
     }
 
-    fn map_handler(&self, _handler: &dyn super::RequestHandler) -> Option<url::Url> {
-        todo!()
+    #[test]
+    pub fn resource_mapper_respond_test() {
+        let mapper = resource_mapper().with_cache_control(CacheControl::MaxAge(3600));
+        let app = Arc::new(WebApplication::default());
+        let result = mapper
+            .map_request(&request("/static/css/site.css", None))
+            .unwrap();
+        let mut cycle = app.create_request_cycle(request("/static/css/site.css", None));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(result.handler.respond(&mut cycle))
+            .unwrap();
+
+        let response = cycle.take_response();
+        assert_eq!(200, response.status);
+        assert_eq!(Some("text/css; charset=utf-8"), response.get_content_type());
+        assert_eq!(
+            Some("public, max-age=3600"),
+            response.get_header("Cache-Control")
+        );
+        assert!(response.get_header("ETag").is_some());
+        assert!(response.get_header("Last-Modified").is_some());
+
+        // The file is only opened on respond, a missing one is a 404.
+        let mut app = WebApplication::default();
+        app.mount_resources(
+            "/static",
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/resources/static"),
+        );
+        let app = Arc::new(app);
+        for uri in ["/static/css/missing.css", "/static/css"] {
+            assert!(mapper.map_request(&request(uri, None)).is_some(), "{}", uri);
+            let response = runtime
+                .block_on(app.process_request(request(uri, None)))
+                .unwrap();
+            assert_eq!(404, response.status, "{}", uri);
+        }
+    }
+
+    #[test]
+    pub fn mount_page_test() {
+        let app = product_app(WebApplication::default());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let response = runtime
+            .block_on(app.process_request(request("/product/5", None)))
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(PRODUCT_HTML, body(response));
+
+        let handler = RenderPageRequestHandler::new(PageProvider::new(
+            ProductPage::markup_type(),
+            Some(PageParameters::new().add("id".to_string(), "7".to_string())),
+        ));
+        let cycle = app.create_request_cycle(request("/", None));
+        // The mount is preferred over the default bookmarkable url.
+        assert_eq!(
+            "/product/7",
+            cycle.map_url_for(&handler).unwrap().to_string()
+        );
+
+        let mut response = runtime
+            .block_on(app.process_request(request("/wicket/bookmarkable/ProductPage", None)))
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert!(matches!(response.take_body(), ResponseBody::Buffered(_)));
     }
 }
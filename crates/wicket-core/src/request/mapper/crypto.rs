@@ -0,0 +1,256 @@
+//! Encrypted, tamper-proof urls, the port of Wicket's CryptoMapper.
+use std::io::{Error, Result};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use wicket_request::request::url::Url;
+
+use super::WICKET_SEGMENT;
+use crate::request::handler::ErrorCodeRequestHandler;
+use crate::request::{
+    Request, RequestHandler, RequestMapper, RequestMapperLogic, RequestMappingResult,
+};
+
+const CRYPT_SEGMENT: &str = "crypt";
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypt the urls produced by the wrapped mappers and decrypt the incoming ones.
+///
+/// The url "/product/5?tab=specs" becomes "/wicket/crypt/<token>", the token being the
+/// base64url nonce and ChaCha20-Poly1305 ciphertext of the original url. A token failing
+/// authentication is answered with 404 and never reaches the wrapped mappers.
+///
+/// Plain urls of pages are answered with 404 as are the "/wicket/..." urls which name
+/// page types, see [CryptoMapper::with_plain_page_urls]. Plain urls of other handlers
+/// eg resources are passed on.
+pub struct CryptoMapper {
+    cipher: ChaCha20Poly1305,
+    mappers: Vec<RequestMapper>,
+    plain_page_urls: bool,
+}
+
+impl CryptoMapper {
+    /// Encrypt the urls of the mappers with the 256 bit key.
+    pub fn new(key: &[u8; 32], mappers: Vec<RequestMapper>) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(&Key::from(*key)),
+            mappers,
+            plain_page_urls: false,
+        }
+    }
+
+    /// Also accept the plain urls of the mounted pages eg bookmarked before encryption.
+    pub fn with_plain_page_urls(mut self) -> Self {
+        self.plain_page_urls = true;
+        self
+    }
+
+    /// Use a random per application key, encrypted urls do not survive a restart.
+    pub fn with_random_key(mappers: Vec<RequestMapper>) -> Self {
+        Self::new(&rand::random(), mappers)
+    }
+
+    /// The `/wicket/crypt/<token>` url of the plain url, a new nonce per call.
+    /// An error when the cipher cannot encrypt the url.
+    pub fn encrypt_url(&self, url: &Url) -> Result<Url> {
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(&Nonce::from(nonce), url.to_string().as_bytes())
+            .map_err(|e| Error::other(format!("Url encryption failed: {}", e)))?;
+        let mut token = nonce.to_vec();
+        token.extend(ciphertext);
+        Ok(Url::new()
+            .add_segment(WICKET_SEGMENT)
+            .add_segment(CRYPT_SEGMENT)
+            .add_segment(URL_SAFE_NO_PAD.encode(token)))
+    }
+
+    /// The original path and query, None when the token is malformed or tampered with.
+    pub fn decrypt_token(&self, token: &str) -> Option<String> {
+        let bytes = URL_SAFE_NO_PAD.decode(token).ok()?;
+        if bytes.len() < NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let nonce: [u8; NONCE_LENGTH] = nonce.try_into().ok()?;
+        let plain = self.cipher.decrypt(&Nonce::from(nonce), ciphertext).ok()?;
+        String::from_utf8(plain).ok()
+    }
+
+    /// As RequestCycle::resolve_request_handler over the wrapped mappers.
+    fn map_wrapped(&self, request: &Request) -> Option<RequestMappingResult> {
+        self.mappers
+            .iter()
+            .filter_map(|mapper| mapper.map_request(request))
+            .filter(|rmr| rmr.compatibility_score > 0)
+            .max_by_key(|rmr| rmr.compatibility_score)
+    }
+}
+
+// Outranks every other mapper, the url is reserved to the CryptoMapper.
+fn not_found() -> RequestMappingResult {
+    RequestMappingResult {
+        handler: Box::new(ErrorCodeRequestHandler::new(404, None)),
+        compatibility_score: i32::MAX,
+    }
+}
+
+impl RequestMapperLogic for CryptoMapper {
+    fn map_request(&self, request: &Request) -> Option<RequestMappingResult> {
+        let url = request.get_url();
+        match url.segments.as_slice() {
+            [wicket, crypt, token] if wicket == WICKET_SEGMENT && crypt == CRYPT_SEGMENT => {
+                let Some(uri) = self
+                    .decrypt_token(token)
+                    .and_then(|decrypted| decrypted.parse::<http::Uri>().ok())
+                else {
+                    return Some(not_found());
+                };
+                let mut parts = request.parts.clone();
+                parts.uri = uri;
+                let decrypted = Request::new(parts, request.body.clone());
+                let handler = self.map_wrapped(&decrypted)?.handler;
+                Some(RequestMappingResult {
+                    handler,
+                    compatibility_score: i32::MAX,
+                })
+            }
+            [wicket, ..] if wicket == WICKET_SEGMENT => Some(not_found()),
+            _ => {
+                let result = self.map_wrapped(request)?;
+                if result.handler.as_page_provider().is_some() && !self.plain_page_urls {
+                    return Some(not_found());
+                }
+                Some(result)
+            }
+        }
+    }
+
+    fn map_handler(&self, handler: &dyn RequestHandler) -> Option<Url> {
+        self.mappers
+            .iter()
+            .rev()
+            .find_map(|mapper| mapper.map_handler(handler))
+            // A url failing encryption has no url rather than the plain one.
+            .and_then(|url| self.encrypt_url(&url).ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use wicket_macro_support::hash_string;
+    use wicket_request::request::mapper::parameter::PageParameters;
+
+    use crate::components::MarkupType;
+    use crate::core::util::resource::locator::FileResourceStreamLocator;
+    use crate::protocol::http::WebApplication;
+    use crate::request::handler::{PageProvider, RenderPageRequestHandler};
+    use crate::request::mapper::{MountedMapper, ResourceMapper};
+    use crate::request::{RequestMapper, RequestMapperLogic};
+    use crate::test_util::request;
+
+    use super::CryptoMapper;
+
+    // Mapping alone does not construct the page, no registration is needed.
+    static PRODUCT: MarkupType = MarkupType {
+        id: hash_string("CryptoProductPage"),
+        name: "CryptoProductPage",
+    };
+
+    fn crypto_mapper(key: &[u8; 32]) -> CryptoMapper {
+        let mounted = MountedMapper::new("/product/${id}", &PRODUCT);
+        CryptoMapper::new(key, vec![RequestMapper::Mounted(mounted)])
+    }
+
+    fn product_handler() -> RenderPageRequestHandler {
+        let params = PageParameters::new()
+            .add("id".to_string(), "5".to_string())
+            .add("tab".to_string(), "specs".to_string());
+        RenderPageRequestHandler::new(PageProvider::new(&PRODUCT, Some(params)))
+    }
+
+    // Respond with the mapped handler, return the status.
+    fn status(mapper: &CryptoMapper, uri: &str) -> u16 {
+        let handler = mapper.map_request(&request(uri, None)).unwrap().handler;
+        let app = Arc::new(WebApplication::default());
+        let mut cycle = app.create_request_cycle(request(uri, None));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(handler.respond(&mut cycle)).ok();
+        cycle.take_response().status
+    }
+
+    #[test]
+    pub fn round_trip_test() {
+        let mapper = crypto_mapper(&[7; 32]);
+        let url = mapper.map_handler(&product_handler()).unwrap().to_string();
+        assert!(url.starts_with("/wicket/crypt/"), "{}", url);
+        assert!(
+            !url.contains("product") && !url.contains("specs"),
+            "{}",
+            url
+        );
+        // A fresh nonce per url.
+        assert_ne!(
+            url,
+            mapper.map_handler(&product_handler()).unwrap().to_string()
+        );
+
+        let result = mapper.map_request(&request(&url, None)).unwrap();
+        let provider = result.handler.as_page_provider().as_ref().unwrap();
+        let params = provider.params.as_ref().unwrap();
+        assert_eq!("5", params.get("id").unwrap().value);
+        assert_eq!("specs", params.get("tab").unwrap().value);
+    }
+
+    #[test]
+    pub fn tampered_url_test() {
+        let mapper = crypto_mapper(&[7; 32]);
+        let url = mapper.map_handler(&product_handler()).unwrap().to_string();
+
+        let mut tampered = url.clone().into_bytes();
+        let last = tampered.last_mut().unwrap();
+        *last = if *last == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert_eq!(404, status(&mapper, &tampered));
+        assert_eq!(404, status(&mapper, "/wicket/crypt/not-a-token"));
+
+        // Another application key.
+        assert_eq!(404, status(&crypto_mapper(&[8; 32]), &url));
+    }
+
+    #[test]
+    pub fn plain_url_test() {
+        // Plain page urls are refused by default.
+        let mapper = crypto_mapper(&[7; 32]);
+        assert_eq!(404, status(&mapper, "/product/5"));
+        assert_eq!(
+            404,
+            status(&mapper, "/wicket/bookmarkable/CryptoProductPage")
+        );
+        assert!(mapper.map_request(&request("/other", None)).is_none());
+
+        let mapper = crypto_mapper(&[7; 32]).with_plain_page_urls();
+        let result = mapper.map_request(&request("/product/5", None)).unwrap();
+        assert!(result.handler.as_page_provider().is_some());
+        assert_eq!(
+            404,
+            status(&mapper, "/wicket/bookmarkable/CryptoProductPage")
+        );
+
+        // Resources are not pages, their plain urls are served.
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/resources/static");
+        let resources = ResourceMapper::new(
+            "/static",
+            Box::new(FileResourceStreamLocator::new(vec![root])),
+        );
+        let mapper = CryptoMapper::new(&[7; 32], vec![RequestMapper::Resource(resources)]);
+        assert_eq!(200, status(&mapper, "/static/css/site.css"));
+    }
+}
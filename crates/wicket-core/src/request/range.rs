@@ -0,0 +1,379 @@
+//! HTTP byte range requests, RFC 9110 section 14, and the validators shared with
+//! conditional requests, section 13.
+//!
+//! Used to resume large downloads and to seek within audio/video served from a
+//! ResourceStream. Only the `bytes` unit is supported.
+use std::io::{Read, Seek, SeekFrom};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Requests with more ranges than this are answered with the full content. Avoids
+/// amplification attacks with many small or overlapping ranges.
+const MAX_RANGES: usize = 32;
+
+/// An inclusive byte range within a resource of known length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range, the end is inclusive.
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The Content-Range header value eg "bytes 0-499/1234".
+    pub fn content_range(&self, complete_length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, complete_length)
+    }
+}
+
+/// The outcome of evaluating a Range header against the resource length.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable Range header, respond 200 with the full content.
+    Full,
+    /// Respond 206 with these ranges.
+    Satisfiable(Vec<ByteRange>),
+    /// Respond 416 with Content-Range: bytes */length.
+    Unsatisfiable,
+}
+
+/// Parse a Range header value for a resource of the given length.
+/// A syntactically invalid header is ignored as required by the RFC.
+pub fn parse_range_header(value: &str, length: u64) -> RangeRequest {
+    let Some((unit, specs)) = value.trim().split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = if first.is_empty() {
+            // Suffix range, the last n bytes.
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || length == 0 {
+                continue;
+            }
+            ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= length {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(length - 1),
+            }
+        };
+        ranges.push(range);
+    }
+
+    if ranges.len() > MAX_RANGES {
+        RangeRequest::Full
+    } else if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Satisfiable(ranges)
+    }
+}
+
+/// A strong entity tag derived from the resource length and modification time.
+pub fn entity_tag(length: u64, last_modified: SystemTime) -> String {
+    let modified = last_modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", modified, length)
+}
+
+/// Evaluate an If-Range header. The Range header is only honoured when the validator
+/// still matches the resource, otherwise the full content is sent.
+pub fn if_range_matches(
+    value: &str,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let value = value.trim();
+    if value.starts_with("W/") {
+        // Weak validators can not be used with ranges.
+        false
+    } else if value.starts_with('"') {
+        etag.is_some_and(|tag| tag == value)
+    } else {
+        match (httpdate::parse_http_date(value), last_modified) {
+            (Ok(date), Some(modified)) => truncate_to_secs(modified) == date,
+            _ => false,
+        }
+    }
+}
+
+/// Evaluate an If-None-Match header with the weak comparison of RFC 9110 13.1.2.
+/// True when the client copy is current and a 304 can be sent.
+pub fn if_none_match_matches(value: &str, etag: Option<&str>) -> bool {
+    let Some(etag) = etag else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    value.trim() == "*" || value.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+/// Evaluate an If-Modified-Since header. Only consulted when there is no If-None-Match.
+/// True when the resource has not changed and a 304 can be sent.
+pub fn not_modified_since(value: &str, last_modified: Option<SystemTime>) -> bool {
+    match (httpdate::parse_http_date(value.trim()), last_modified) {
+        (Ok(date), Some(modified)) => truncate_to_secs(modified) <= date,
+        _ => false,
+    }
+}
+
+/// HTTP dates have a one second resolution.
+pub fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| UNIX_EPOCH + std::time::Duration::from_secs(d.as_secs()))
+        .unwrap_or(time)
+}
+
+enum Part {
+    Bytes(Vec<u8>),
+    Range(ByteRange),
+}
+
+/// Streams the multipart/byteranges body of a 206 response, seeking to each range
+/// of the underlying resource in turn.
+pub struct RangesReader<R> {
+    inner: R,
+    parts: Vec<Part>,
+    part_index: usize,
+    // Bytes of the current part already read.
+    offset: u64,
+}
+
+impl<R: Read + Seek> RangesReader<R> {
+    /// Build the reader. Return it with the boundary and the total body length.
+    pub fn new(
+        inner: R,
+        ranges: &[ByteRange],
+        content_type: Option<&str>,
+        complete_length: u64,
+    ) -> (Self, String, u64) {
+        let boundary = format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        );
+        let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+        for range in ranges {
+            let mut header = format!("\r\n--{}\r\n", boundary);
+            if let Some(ct) = content_type {
+                header.push_str(&format!("Content-Type: {}\r\n", ct));
+            }
+            header.push_str(&format!(
+                "Content-Range: {}\r\n\r\n",
+                range.content_range(complete_length)
+            ));
+            parts.push(Part::Bytes(header.into_bytes()));
+            parts.push(Part::Range(*range));
+        }
+        parts.push(Part::Bytes(
+            format!("\r\n--{}--\r\n", boundary).into_bytes(),
+        ));
+
+        let body_length = parts
+            .iter()
+            .map(|p| match p {
+                Part::Bytes(b) => b.len() as u64,
+                Part::Range(r) => r.length(),
+            })
+            .sum();
+
+        let reader = Self {
+            inner,
+            parts,
+            part_index: 0,
+            offset: 0,
+        };
+        (reader, boundary, body_length)
+    }
+}
+
+impl<R: Read + Seek> Read for RangesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some(part) = self.parts.get(self.part_index) {
+            let read = match part {
+                Part::Bytes(bytes) => {
+                    let remaining = &bytes[self.offset as usize..];
+                    let n = remaining.len().min(buf.len());
+                    buf[..n].copy_from_slice(&remaining[..n]);
+                    n
+                }
+                Part::Range(range) => {
+                    if self.offset == 0 {
+                        self.inner.seek(SeekFrom::Start(range.start))?;
+                    }
+                    let remaining = range.length() - self.offset;
+                    let max = remaining.min(buf.len() as u64) as usize;
+                    let n = self.inner.read(&mut buf[..max])?;
+                    if n == 0 && max > 0 {
+                        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+                    }
+                    n
+                }
+            };
+
+            let part_length = match part {
+                Part::Bytes(bytes) => bytes.len() as u64,
+                Part::Range(range) => range.length(),
+            };
+            self.offset += read as u64;
+            if self.offset == part_length {
+                self.part_index += 1;
+                self.offset = 0;
+            }
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    pub fn parse_range_header_test() {
+        assert_eq!(
+            RangeRequest::Satisfiable(vec![ByteRange { start: 0, end: 499 }]),
+            parse_range_header("bytes=0-499", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Satisfiable(vec![ByteRange {
+                start: 900,
+                end: 999
+            }]),
+            parse_range_header("bytes=900-", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Satisfiable(vec![ByteRange {
+                start: 800,
+                end: 999
+            }]),
+            parse_range_header("bytes=-200", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Satisfiable(vec![ByteRange { start: 0, end: 999 }]),
+            parse_range_header("bytes=-2000", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Satisfiable(vec![
+                ByteRange { start: 0, end: 0 },
+                ByteRange {
+                    start: 998,
+                    end: 999
+                }
+            ]),
+            parse_range_header("bytes=0-0, 998-5000", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Unsatisfiable,
+            parse_range_header("bytes=1000-", 1000)
+        );
+        assert_eq!(
+            RangeRequest::Unsatisfiable,
+            parse_range_header("bytes=-0", 1000)
+        );
+        assert_eq!(RangeRequest::Full, parse_range_header("bytes=5-1", 1000));
+        assert_eq!(RangeRequest::Full, parse_range_header("items=0-1", 1000));
+        assert_eq!(RangeRequest::Full, parse_range_header("bytes=a-b", 1000));
+        let many = format!("bytes={}", vec!["0-1"; MAX_RANGES + 1].join(","));
+        assert_eq!(RangeRequest::Full, parse_range_header(&many, 1000));
+    }
+
+    #[test]
+    pub fn if_range_test() {
+        let modified = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let etag = entity_tag(10, modified);
+        assert!(if_range_matches(&etag, Some(&etag), Some(modified)));
+        assert!(!if_range_matches("\"other\"", Some(&etag), Some(modified)));
+        assert!(!if_range_matches(
+            &format!("W/{}", etag),
+            Some(&etag),
+            Some(modified)
+        ));
+        let date = httpdate::fmt_http_date(modified);
+        assert!(if_range_matches(&date, Some(&etag), Some(modified)));
+        let older = httpdate::fmt_http_date(modified - Duration::from_secs(5));
+        assert!(!if_range_matches(&older, Some(&etag), Some(modified)));
+    }
+
+    #[test]
+    pub fn conditional_get_test() {
+        let modified = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let etag = entity_tag(10, modified);
+        assert!(if_none_match_matches(&etag, Some(&etag)));
+        assert!(if_none_match_matches(
+            &format!("\"a\", W/{}", etag),
+            Some(&etag)
+        ));
+        assert!(if_none_match_matches("*", Some(&etag)));
+        assert!(!if_none_match_matches("\"a\"", Some(&etag)));
+        assert!(!if_none_match_matches("*", None));
+
+        let date = httpdate::fmt_http_date(modified);
+        assert!(not_modified_since(&date, Some(modified)));
+        let later = httpdate::fmt_http_date(modified + Duration::from_secs(5));
+        assert!(not_modified_since(&later, Some(modified)));
+        let older = httpdate::fmt_http_date(modified - Duration::from_secs(5));
+        assert!(!not_modified_since(&older, Some(modified)));
+        assert!(!not_modified_since("yesterday", Some(modified)));
+    }
+
+    #[test]
+    pub fn ranges_reader_test() {
+        let data = Cursor::new(b"0123456789".to_vec());
+        let ranges = [
+            ByteRange { start: 0, end: 1 },
+            ByteRange { start: 7, end: 9 },
+        ];
+        let (mut reader, boundary, length) =
+            RangesReader::new(data, &ranges, Some("text/plain"), 10);
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 7-9/10\r\n\r\n789\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(expected, body);
+        assert_eq!(expected.len() as u64, length);
+    }
+}
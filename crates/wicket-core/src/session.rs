@@ -1,29 +1,107 @@
+use std::any::{Any, TypeId};
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use tokio::sync::Mutex;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use subtle::ConstantTimeEq;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
 
 use crate::components::WebPage;
+use crate::markup::dimensions::Locale;
+use crate::page_store::{PageStore, SimplePageStore};
+use crate::request::Response;
 use dashmap::DashMap;
-use rand::random;
 
 pub mod page_factory;
 
 const FIVE_MIN_SECS: u16 = 300;
+const SESSION_ID_LENGTH: usize = 16;
+const MAX_BUFFERED_RESPONSES: usize = 10;
+
+/// A 128 bit session identifier from the thread CSPRNG, base64url in the cookie.
+///
+/// Ids are compared in constant time, the registry lookup does not reveal how much of a
+/// guessed id matches a live one.
+#[derive(Clone, Copy, Eq)]
+pub struct SessionId([u8; SESSION_ID_LENGTH]);
+
+impl SessionId {
+    /// A new random session id.
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// Decode the cookie value, None unless exactly 128 bits of base64url.
+    pub fn parse(value: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+        Some(Self(bytes.try_into().ok()?))
+    }
+}
+
+impl PartialEq for SessionId {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Hash for SessionId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(self.0))
+    }
+}
+
+// Keep live ids out of the logs.
+impl Debug for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SessionId(..)")
+    }
+}
+
+/// Notified of the session life cycle eg to audit logouts or release the resources
+/// held for a session.
+pub trait SessionListener: Send + Sync {
+    fn on_created(&self, _session_id: SessionId) {}
+
+    /// The session was idle for longer than the timeout, see
+    /// [SessionRegistry::spawn_sweeper].
+    fn on_expired(&self, _session_id: SessionId, _session: &mut SessionData) {}
+
+    /// The session was ended by the application eg a logout.
+    fn on_invalidated(&self, _session_id: SessionId, _session: &mut SessionData) {}
+}
 
 /// Application sessions container.
 pub struct SessionRegistry {
     // Epoch seconds.
     app_start: u64,
-    // Key: SessionId u32
-    sessions: DashMap<u32, Arc<Mutex<SessionData>>>,
+    sessions: DashMap<SessionId, Arc<Mutex<SessionData>>>,
+    listeners: RwLock<Vec<Box<dyn SessionListener>>>,
 }
 
 pub struct SessionData {
     last_touched: u16,
-    // Key: PageId (u16) -> Value: History of that page
-    pages: HashMap<u16, Vec<Box<dyn WebPage>>>,
+    // The page instances and their versions.
+    pages: Box<dyn PageStore>,
+    // The responses rendered for a redirect by their url, oldest first.
+    buffered_responses: Vec<(String, Response)>,
+    next_page_id: u16,
+    // Key: the type of the attribute, one value per type.
+    attributes: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    // None negotiates the locale of each request from Accept-Language.
+    locale: Option<Locale>,
+    style: Option<String>,
+    variation: Option<String>,
 }
 
 impl Default for SessionRegistry {
@@ -31,6 +109,7 @@ impl Default for SessionRegistry {
         Self {
             app_start: Self::calc_app_start(),
             sessions: DashMap::new(),
+            listeners: RwLock::default(),
         }
     }
 }
@@ -52,26 +131,152 @@ impl SessionRegistry {
         ((now - self.app_start) / u64::from(FIVE_MIN_SECS)) as u16
     }
 
-    pub fn new_session(&self) -> u32 {
-        let last_touched = self.get_current_5min_tick();
-        loop {
-            let session_id: u32 = random();
-            let new_data = SessionData {
-                last_touched,
-                pages: HashMap::new(),
+    /// The number of live sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// True without a live session.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Notify the listener of the session events.
+    pub fn add_listener(&self, listener: Box<dyn SessionListener>) {
+        self.listeners
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(listener);
+    }
+
+    fn notify(&self, mut event: impl FnMut(&dyn SessionListener)) {
+        let listeners = self
+            .listeners
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for listener in listeners.iter() {
+            event(listener.as_ref());
+        }
+    }
+
+    /// Create a session storing its pages in memory.
+    pub fn new_session(&self) -> SessionId {
+        self.new_session_with(|_| Box::new(SimplePageStore::default()))
+    }
+
+    /// Create a session storing its pages in the store, see
+    /// [crate::page_store::new_page_store].
+    pub fn new_session_with(
+        &self,
+        page_store: impl FnOnce(SessionId) -> Box<dyn PageStore>,
+    ) -> SessionId {
+        let session_id = loop {
+            let session_id = SessionId::generate();
+            if let dashmap::Entry::Vacant(entry) = self.sessions.entry(session_id) {
+                let new_data = SessionData {
+                    last_touched: self.get_current_5min_tick(),
+                    pages: page_store(session_id),
+                    buffered_responses: Vec::new(),
+                    next_page_id: 0,
+                    attributes: HashMap::new(),
+                    locale: None,
+                    style: None,
+                    variation: None,
+                };
+                entry.insert(Arc::from(Mutex::new(new_data)));
+                break session_id;
+            }
+        };
+        self.notify(|listener| listener.on_created(session_id));
+        session_id
+    }
+
+    /// Lock the session for a request and mark it as used.
+    pub async fn lock_session(
+        &self,
+        session_id: SessionId,
+    ) -> Option<OwnedMutexGuard<SessionData>> {
+        let mut session = self.get_session_handle(session_id)?.lock_owned().await;
+        session.last_touched = self.get_current_5min_tick();
+        Some(session)
+    }
+
+    /// Move the session to a new random id, return the new id.
+    pub fn replace_session_id(&self, session_id: SessionId) -> Option<SessionId> {
+        let (_, handle) = self.sessions.remove(&session_id)?;
+        Some(self.insert_with_new_id(handle))
+    }
+
+    /// Remove the session locked by the caller and notify the listeners.
+    pub fn invalidate_session(&self, session_id: SessionId, session: &mut SessionData) {
+        if self.sessions.remove(&session_id).is_some() {
+            self.notify(|listener| listener.on_invalidated(session_id, session));
+        }
+    }
+
+    /// Remove the sessions not used for the idle timeout, return the number removed.
+    /// A session is idle for at least the timeout, up to a 5 minute tick longer, before
+    /// it is removed. Sessions locked by a request are in use and kept.
+    pub async fn sweep(&self, idle_timeout: Duration) -> usize {
+        let idle_ticks = idle_timeout.as_secs().div_ceil(u64::from(FIVE_MIN_SECS));
+        let now = self.get_current_5min_tick();
+        let is_idle = |handle: &Arc<Mutex<SessionData>>| {
+            handle
+                .try_lock()
+                .is_ok_and(|session| u64::from(now.wrapping_sub(session.last_touched)) > idle_ticks)
+        };
+
+        // Listeners are called without holding the map shards.
+        let candidates: Vec<SessionId> = self
+            .sessions
+            .iter()
+            .filter(|entry| is_idle(entry.value()))
+            .map(|entry| *entry.key())
+            .collect();
+        let mut removed = 0;
+        for session_id in candidates {
+            // Touched since the scan.
+            let Some((_, handle)) = self.sessions.remove_if(&session_id, |_, h| is_idle(h)) else {
+                continue;
             };
+            removed += 1;
+            let mut session = handle.lock().await;
+            self.notify(|listener| listener.on_expired(session_id, &mut session));
+        }
+        removed
+    }
 
-            if self
-                .sessions
-                .insert(session_id, Arc::from(Mutex::new(new_data)))
-                .is_none()
-            {
+    /// Sweep the idle sessions every interval until the task is aborted.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn spawn_sweeper(
+        self: &Arc<Self>,
+        idle_timeout: Duration,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let registry = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                registry.sweep(idle_timeout).await;
+            }
+        })
+    }
+
+    fn insert_with_new_id(&self, handle: Arc<Mutex<SessionData>>) -> SessionId {
+        loop {
+            let session_id = SessionId::generate();
+            if let dashmap::Entry::Vacant(entry) = self.sessions.entry(session_id) {
+                entry.insert(handle);
                 return session_id;
             }
         }
     }
 
-    pub fn get_session_handle(&self, session_id: u32) -> Option<Arc<Mutex<SessionData>>> {
+    /// The handle of the session, None when it expired.
+    pub fn get_session_handle(&self, session_id: SessionId) -> Option<Arc<Mutex<SessionData>>> {
         let handle = {
             let dash_shard_handle = self.sessions.get(&session_id)?;
             let session_mut = dash_shard_handle.value();
@@ -81,7 +286,8 @@ impl SessionRegistry {
         Some(handle)
     }
 
-    pub async fn with<F, Fut, R>(&self, session_id: u32, f: F) -> Option<R>
+    /// Run the function with the session and touch it, None when it expired.
+    pub async fn with<F, Fut, R>(&self, session_id: SessionId, f: F) -> Option<R>
     where
         F: FnOnce(Arc<Mutex<SessionData>>) -> Fut,
         Fut: std::future::Future<Output = R>,
@@ -101,10 +307,331 @@ impl SessionData {
         self.last_touched
     }
 
-    pub fn get_page(&self, page_instance: u16, page_version: u16) -> Option<&dyn WebPage> {
+    /// The version of the page instance, restored from the page store if needed.
+    pub fn get_page(
+        &mut self,
+        page_instance: u16,
+        page_version: u16,
+    ) -> io::Result<Option<&(dyn WebPage + 'static)>> {
+        self.pages.get_page(page_instance, page_version)
+    }
+
+    /// The most recent version of the page instance.
+    pub fn get_latest_page(
+        &mut self,
+        page_instance: u16,
+    ) -> io::Result<Option<&(dyn WebPage + 'static)>> {
+        match self.pages.get_latest_version(page_instance) {
+            Some(version) => self.pages.get_page(page_instance, version),
+            None => Ok(None),
+        }
+    }
+
+    /// The most recent version of the page instance, None for an unknown instance.
+    pub fn get_latest_version(&self, page_instance: u16) -> Option<u16> {
+        self.pages.get_latest_version(page_instance)
+    }
+
+    /// Store a new page instance at version 0, return its page id. The ids wrap around
+    /// and skip the instances still stored, an error when all ids are in use.
+    pub fn add_page(&mut self, page: Box<dyn WebPage>) -> io::Result<u16> {
+        let page_id = (0..=u16::MAX)
+            .map(|offset| self.next_page_id.wrapping_add(offset))
+            .find(|page_id| self.pages.get_latest_version(*page_id).is_none())
+            .ok_or_else(|| io::Error::other("All page ids of the session are in use."))?;
+        self.next_page_id = page_id.wrapping_add(1);
+        self.pages.store_page(page_id, 0, page)?;
+        Ok(page_id)
+    }
+
+    /// Store a change of a version of an existing page instance, return the new version.
+    /// See [PageStore::store_page].
+    pub fn store_page(
+        &mut self,
+        page_instance: u16,
+        page_version: u16,
+        page: Box<dyn WebPage>,
+    ) -> io::Result<Option<u16>> {
+        if self.pages.get_latest_version(page_instance).is_none() {
+            return Ok(None);
+        }
         self.pages
-            .get(&page_instance)
-            .and_then(|vec| vec.get(page_version as usize))
-            .map(|boxed| &**boxed)
+            .store_page(page_instance, page_version, page)
+            .map(Some)
+    }
+
+    /// Keep the response rendered for the redirect to the url, see
+    /// [crate::settings::RenderStrategy::RedirectToBuffer]. Responses of redirects never
+    /// followed are dropped once there are more than MAX_BUFFERED_RESPONSES.
+    pub fn buffer_response(&mut self, url: String, response: Response) {
+        self.buffered_responses.retain(|(u, _)| *u != url);
+        if self.buffered_responses.len() >= MAX_BUFFERED_RESPONSES {
+            self.buffered_responses.remove(0);
+        }
+        self.buffered_responses.push((url, response));
+    }
+
+    /// Remove the response buffered for the url, a buffer is served once.
+    pub fn take_buffered_response(&mut self, url: &str) -> Option<Response> {
+        let index = self.buffered_responses.iter().position(|(u, _)| u == url)?;
+        Some(self.buffered_responses.remove(index).1)
+    }
+
+    /// The locale of the markup, None when negotiated from the request.
+    pub fn get_locale(&self) -> Option<&Locale> {
+        self.locale.as_ref()
+    }
+
+    /// Fix the locale of the markup, None to negotiate it from the request.
+    pub fn set_locale(&mut self, locale: Option<Locale>) {
+        self.locale = locale;
+    }
+
+    /// The markup style, see [SessionData::set_style].
+    pub fn get_style(&self) -> Option<&str> {
+        self.style.as_deref()
+    }
+
+    /// The markup style eg "dark" selects Page.dark.html.
+    pub fn set_style(&mut self, style: Option<&str>) {
+        self.style = style.map(str::to_string);
+    }
+
+    /// The markup variation, see [SessionData::set_variation].
+    pub fn get_variation(&self) -> Option<&str> {
+        self.variation.as_deref()
+    }
+
+    /// The markup variation eg "wide" selects Page.wide.html.
+    pub fn set_variation(&mut self, variation: Option<&str>) {
+        self.variation = variation.map(str::to_string);
+    }
+
+    /// Store the session attribute of the type eg the logged in user, return the
+    /// previous value. Components reach the session through the RequestCycle
+    /// eg `cycle.get_session().and_then(|s| s.get::<CurrentUser>())`.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.attributes
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    /// The session attribute of the type.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.attributes
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// The mutable session attribute of the type.
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.attributes
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Remove and return the session attribute of the type.
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.attributes
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::protocol::http::WebApplication;
+    use crate::test_util::{product_app, request, session_cookie, ProductPage};
+
+    use super::{SessionData, SessionId, SessionListener, SessionRegistry};
+
+    #[test]
+    pub fn session_id_test() {
+        let id = SessionId::generate();
+        let encoded = id.to_string();
+        // 128 bits of url safe base64 without padding.
+        assert_eq!(22, encoded.len());
+        assert!(encoded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(Some(id), SessionId::parse(&encoded));
+        assert_ne!(id, SessionId::generate());
+
+        for invalid in ["", "abc", "8c", &encoded[..21], &format!("{}AA", encoded)] {
+            assert_eq!(None, SessionId::parse(invalid), "{}", invalid);
+        }
+        assert_eq!("SessionId(..)", format!("{:?}", id));
+
+        let registry = SessionRegistry::default();
+        let id = registry.new_session();
+        assert!(registry
+            .get_session_handle(SessionId::parse(&id.to_string()).unwrap())
+            .is_some());
+        assert!(registry.get_session_handle(SessionId::generate()).is_none());
+    }
+
+    #[test]
+    pub fn attribute_test() {
+        #[derive(Debug, PartialEq)]
+        struct CurrentUser(&'static str);
+        struct Cart(Vec<u32>);
+
+        let registry = SessionRegistry::default();
+        let handle = registry.get_session_handle(registry.new_session()).unwrap();
+        let mut session = handle.try_lock().unwrap();
+
+        assert_eq!(None, session.insert(CurrentUser("ann")));
+        session.insert(Cart(vec![1]));
+        session.get_mut::<Cart>().unwrap().0.push(2);
+        assert_eq!(Some(&CurrentUser("ann")), session.get::<CurrentUser>());
+        assert_eq!(vec![1, 2], session.get::<Cart>().unwrap().0);
+
+        assert_eq!(Some(CurrentUser("ann")), session.insert(CurrentUser("bob")));
+        assert_eq!(Some(CurrentUser("bob")), session.remove::<CurrentUser>());
+        assert_eq!(None, session.get::<CurrentUser>());
+        assert!(session.get::<u32>().is_none());
+    }
+
+    #[test]
+    pub fn add_page_test() {
+        let registry = SessionRegistry::default();
+        let handle = registry.get_session_handle(registry.new_session()).unwrap();
+        let mut session = handle.try_lock().unwrap();
+        let page = || Box::new(ProductPage { count: 0 });
+
+        assert_eq!(0, session.add_page(page()).unwrap());
+        assert_eq!(1, session.add_page(page()).unwrap());
+        session.next_page_id = u16::MAX;
+        assert_eq!(u16::MAX, session.add_page(page()).unwrap());
+        // The ids wrap around, the stored instances 0 and 1 are skipped.
+        assert_eq!(2, session.add_page(page()).unwrap());
+        assert_eq!(Some(0), session.get_latest_version(0));
+    }
+
+    #[derive(Default)]
+    struct Audit(Arc<Mutex<Vec<&'static str>>>);
+
+    impl SessionListener for Audit {
+        fn on_created(&self, _session_id: SessionId) {
+            self.0.lock().unwrap().push("created");
+        }
+
+        fn on_expired(&self, _session_id: SessionId, _session: &mut SessionData) {
+            self.0.lock().unwrap().push("expired");
+        }
+
+        fn on_invalidated(&self, _session_id: SessionId, _session: &mut SessionData) {
+            self.0.lock().unwrap().push("invalidated");
+        }
+    }
+
+    #[test]
+    pub fn sweep_test() {
+        let registry = SessionRegistry::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        registry.add_listener(Box::new(Audit(events.clone())));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let idle = registry.new_session();
+            let active = registry.new_session();
+            let locked = registry.new_session();
+            let logout = registry.new_session();
+            let now = registry.get_current_5min_tick();
+            for session_id in [idle, locked] {
+                let handle = registry.get_session_handle(session_id).unwrap();
+                // Untouched for 35 minutes.
+                handle.lock().await.last_touched = now.wrapping_sub(7);
+            }
+            {
+                let handle = registry.get_session_handle(active).unwrap();
+                handle.lock().await.last_touched = now.wrapping_sub(6);
+            }
+
+            // An idle session in use by a request.
+            let guard = registry
+                .get_session_handle(locked)
+                .unwrap()
+                .lock_owned()
+                .await;
+
+            assert_eq!(1, registry.sweep(Duration::from_secs(30 * 60)).await);
+            assert!(registry.get_session_handle(idle).is_none());
+            assert!(registry.get_session_handle(active).is_some());
+            assert!(registry.get_session_handle(locked).is_some());
+            drop(guard);
+            assert_eq!(1, registry.sweep(Duration::from_secs(30 * 60)).await);
+
+            let mut session = registry.lock_session(logout).await.unwrap();
+            registry.invalidate_session(logout, &mut session);
+            assert!(registry.get_session_handle(logout).is_none());
+        });
+
+        assert_eq!(
+            vec![
+                "created",
+                "created",
+                "created",
+                "created",
+                "expired",
+                "expired",
+                "invalidated"
+            ],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    pub async fn stateful_page_test() {
+        let app = product_app(WebApplication::default());
+        let session_id = app.sessions.new_session();
+
+        let process = |uri: &str, session_id| app.process_request(request(uri, session_id));
+
+        // The new page is stored as instance 0.
+        let response = process("/product/5", Some(session_id)).await.unwrap();
+        assert_eq!(200, response.status);
+        let response = process("/wicket/page?0", Some(session_id)).await.unwrap();
+        assert_eq!(200, response.status);
+
+        // The listener stores a new version and redirects to the buffered render.
+        let response = process("/product/5?0-0.click-form-link", Some(session_id))
+            .await
+            .unwrap();
+        assert_eq!(302, response.status);
+        let response = process("/product/5?0", Some(session_id)).await.unwrap();
+        assert_eq!(200, response.status);
+        {
+            let handle = app.sessions.get_session_handle(session_id).unwrap();
+            let mut session = handle.lock().await;
+            assert!(session.get_page(0, 1).unwrap().is_some());
+            assert!(session.get_page(1, 0).unwrap().is_none());
+        }
+
+        // No listener of the component.
+        let response = process("/wicket/page?0.other-link", Some(session_id))
+            .await
+            .unwrap();
+        assert_eq!(404, response.status);
+        // Unknown instance and no session.
+        let response = process("/wicket/page?9", Some(session_id)).await.unwrap();
+        assert_eq!(410, response.status);
+        let response = process("/wicket/page?0", None).await.unwrap();
+        assert_eq!(410, response.status);
+
+        // A new page creates the session to store it in.
+        let response = process("/product/5", None).await.unwrap();
+        let new_id = session_cookie(&response).unwrap();
+        assert_ne!(session_id, new_id);
+        let response = process("/wicket/page?0", Some(new_id)).await.unwrap();
+        assert_eq!(200, response.status);
+        assert!(response.get_headers().is_none());
     }
 }
@@ -3,6 +3,7 @@ use std::{
     sync::OnceLock,
 };
 
+use wicket_macro_support::hash_string;
 use wicket_request::request::mapper::parameter::PageParameters;
 
 use crate::components::{FromPageParameters, MarkupType, WebPage};
@@ -42,8 +43,25 @@ pub fn construct_page(id: u16, params: Option<PageParameters>) -> Option<Box<dyn
     page_inventory.get(&id).map(|pe| (pe.constructor)(params))
 }
 
+/// Find a registered page by its bare type name eg "ProductPage".
+pub fn find_page_entry(name: &str) -> Option<&'static PageEntry> {
+    let page_inventory = PAGE_FACTORY.get_or_init(&create_page_factory_map);
+    page_inventory
+        .get(&hash_string(name))
+        .copied()
+        .filter(|pe| pe.id.name == name)
+}
+
+/// The registered page of the page type id.
+pub fn get_page_entry(id: u16) -> Option<&'static PageEntry> {
+    let page_inventory = PAGE_FACTORY.get_or_init(&create_page_factory_map);
+    page_inventory.get(&id).copied()
+}
+
 pub struct PageEntry {
     pub id: &'static MarkupType,
+    /// The module_path!() of the page struct eg "myapp::admin".
+    pub module_path: &'static str,
     pub constructor: WebPageConstructor,
 }
 
@@ -104,13 +122,7 @@ mod test {
     }
 
     impl MarkupLookup for TestPage {
-        fn lookup_markup(
-            &self,
-            _style: Option<u8>,
-            _variation: Option<u8>,
-            _lang: Option<u8>,
-            _country: Option<u8>,
-        ) -> Option<&MarkupResource> {
+        fn markup_resources(&self) -> &[MarkupResource] {
             unreachable!()
         }
     }
@@ -130,6 +142,7 @@ mod test {
     inventory::submit! {
         PageEntry {
             id: &TESTPAGE_ID,
+            module_path: module_path!(),
             constructor: |_params| {
                 Box::new(TestPage{})
             }
@@ -183,6 +196,17 @@ mod test {
 
     impl WebPage for ParameterizedPage {}
 
+    #[test]
+    pub fn find_page_entry_test() {
+        let entry = find_page_entry("TestPage").unwrap();
+        assert_eq!(TESTPAGE_ID.id, entry.id.id);
+        assert_eq!(
+            "wicket_core::session::page_factory::test",
+            entry.module_path
+        );
+        assert!(find_page_entry("NoSuchPage").is_none());
+    }
+
     #[test]
     pub fn webpage_parameter_test() {
         let param = PageParameters::new().add("data".to_string(), "abc123".to_string());
@@ -1,4 +1,15 @@
-use crate::{components::MarkupIdGenerator, markup::MarkupFactory};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cookie::SameSite;
+
+use crate::page_store::{PageSerializer, PageStore};
+use crate::session::SessionId;
+use crate::{
+    components::{MarkupIdGenerator, MarkupType},
+    markup::MarkupFactory,
+};
 
 #[derive(Default)]
 pub struct MarkupSettings {
@@ -31,3 +42,204 @@ pub struct MarkupSettings {
     // org.apache.wicket.Component#setOutputMarkupId(boolean) #setOutputMarkupId(true)}
     pub markup_id_generator: MarkupIdGenerator,
 }
+
+/// The attributes of the SESSION_ID cookie.
+pub struct SessionCookieSettings {
+    // Hide the cookie from javascript.
+    pub http_only: bool,
+
+    // Only send the cookie over https.
+    pub secure: bool,
+
+    pub same_site: SameSite,
+
+    pub path: String,
+
+    // None for a cookie removed when the browser closes.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for SessionCookieSettings {
+    fn default() -> Self {
+        Self {
+            http_only: true,
+            secure: false,
+            same_site: SameSite::Lax,
+            path: "/".to_string(),
+            max_age: None,
+        }
+    }
+}
+
+/// The expiry of idle sessions, see [crate::session::SessionRegistry::spawn_sweeper].
+pub struct SessionSettings {
+    // Remove a session not used for this long.
+    pub idle_timeout: Duration,
+
+    // How often the sessions are checked.
+    pub sweep_interval: Duration,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(30 * 60),
+            sweep_interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// How a page changed by a listener is rendered, see
+/// [crate::request::handler::ListenerRequestHandler].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderStrategy {
+    /// Render in the listener request, the browser url remains the listener url and a
+    /// reload repeats the listener.
+    OnePassRender,
+    /// Render in the listener request, buffer the markup in the session and redirect to
+    /// the page url, the buffer is served to the redirected GET. Post, redirect, get
+    /// without rendering twice.
+    #[default]
+    RedirectToBuffer,
+    /// Redirect to the page url and render the page on the redirected GET.
+    RedirectToRender,
+}
+
+/// The targets allowed for [crate::request::cycle::RedirectAction::RedirectUrl], guarding
+/// against open redirects eg to a url taken from a request parameter.
+#[derive(Default)]
+pub struct RedirectSettings {
+    // The hosts of absolute http(s) urls eg "accounts.example.com", matched case
+    // insensitively. Absolute paths of the application eg "/login" are always allowed.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl RedirectSettings {
+    /// True for a path of the application or a url of an allowed host.
+    pub fn is_allowed(&self, url: &str) -> bool {
+        // Control characters could split the Location header, a backslash is read as
+        // a slash by browsers eg "/\evil.com".
+        if url.chars().any(|c| c.is_control() || c == '\\') {
+            return false;
+        }
+        if url.starts_with('/') && !url.starts_with("//") {
+            return true;
+        }
+        let Ok(uri) = url.parse::<http::Uri>() else {
+            return false;
+        };
+        let is_http = matches!(uri.scheme_str(), Some("http" | "https"));
+        is_http
+            && uri.host().is_some_and(|host| {
+                self.allowed_hosts
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(host))
+            })
+    }
+}
+
+/// The handling of a page instance no longer in the session, see
+/// [crate::request::handler::PageExpiredError].
+pub struct PageSettings {
+    // Render a new page from the page type and parameters of a mounted or bookmarkable
+    // url when its page instance has expired, the listener of the url is not invoked.
+    pub recreate_bookmarkable_pages_after_expiry: bool,
+
+    // The page rendered when the page instance has expired and cannot be recreated eg
+    // "/wicket/page?3". None responds with 410 Gone.
+    pub page_expired_error_page: Option<&'static MarkupType>,
+}
+
+impl Default for PageSettings {
+    fn default() -> Self {
+        Self {
+            recreate_bookmarkable_pages_after_expiry: true,
+            page_expired_error_page: None,
+        }
+    }
+}
+
+/// The response to a failed request, see
+/// [crate::request::exception::DefaultExceptionMapper].
+#[derive(Default)]
+pub struct ExceptionSettings {
+    // Describe the failure and the request in the response instead of rendering the
+    // error pages, not for production.
+    pub development_mode: bool,
+
+    // The page rendered with a 404 for a url without a handler. None responds with the
+    // status alone.
+    pub not_found_page: Option<&'static MarkupType>,
+
+    // The page rendered with a 403 for a request denied eg a redirect to a host not in
+    // the RedirectSettings.
+    pub access_denied_page: Option<&'static MarkupType>,
+
+    // The page rendered with a 500 for a handler error or panic.
+    pub internal_error_page: Option<&'static MarkupType>,
+}
+
+/// The page store of each session, see [crate::page_store::new_page_store].
+#[derive(Clone, Default)]
+pub enum PageStoreType {
+    /// Every version of every page in memory, see [crate::page_store::SimplePageStore].
+    #[default]
+    Memory,
+    /// The recent versions in memory and the older versions serialized to disk within
+    /// the limits of the settings, see [crate::page_store::disk::DiskPageStore]. The
+    /// file I/O blocks the request while the session is locked.
+    Disk(Arc<dyn PageSerializer>),
+    /// An application provided store.
+    Custom(Arc<dyn Fn(SessionId) -> Box<dyn PageStore> + Send + Sync>),
+}
+
+/// The page store of each session and the limits of the
+/// [crate::page_store::disk::DiskPageStore].
+pub struct PageStoreSettings {
+    pub store_type: PageStoreType,
+
+    // The most recently used page versions kept in memory, older versions are written
+    // to disk.
+    pub max_pages_in_memory: usize,
+
+    // The disk budget of a session, the oldest versions are deleted to stay within it.
+    pub max_size_per_session: u64,
+
+    // Each session stores its pages in a sub directory with a random name, not the
+    // session id.
+    pub dir: PathBuf,
+}
+
+impl Default for PageStoreSettings {
+    fn default() -> Self {
+        Self {
+            store_type: PageStoreType::Memory,
+            max_pages_in_memory: 10,
+            max_size_per_session: 10 * 1024 * 1024,
+            dir: std::env::temp_dir().join("wicket-pages"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RedirectSettings;
+
+    #[test]
+    pub fn redirect_settings_test() {
+        let settings = RedirectSettings {
+            allowed_hosts: vec!["accounts.example.com".to_string()],
+        };
+        assert!(settings.is_allowed("/login?next=%2Fhome"));
+        assert!(settings.is_allowed("https://Accounts.Example.com/sso"));
+        assert!(settings.is_allowed("http://accounts.example.com:8080/"));
+
+        assert!(!settings.is_allowed("//evil.com/x"));
+        assert!(!settings.is_allowed("/\\evil.com"));
+        assert!(!settings.is_allowed("https://evil.com/accounts.example.com"));
+        assert!(!settings.is_allowed("https://accounts.example.com.evil.com/"));
+        assert!(!settings.is_allowed("javascript:alert(1)"));
+        assert!(!settings.is_allowed("/ok\r\nSet-Cookie: a=b"));
+        assert!(!settings.is_allowed("login"));
+    }
+}
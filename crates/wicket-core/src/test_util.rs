@@ -0,0 +1,126 @@
+//! The page and request builders shared by the tests of the crate.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use wicket_macro::wicket_page;
+use wicket_request::request::mapper::parameter::PageParameters;
+
+use crate::components::{ComponentId, FromPageParameters, MarkupContainer, WebPage};
+use crate::protocol::http::WebApplication;
+use crate::request::cycle::{RedirectAction, RequestCycle};
+use crate::request::{Request, RequestBody, Response, ResponseBody};
+use crate::session::SessionId;
+
+/// The markup of [ProductPage].
+pub(crate) const PRODUCT_HTML: &str = "<html><body>Product</body></html>\n";
+
+/// The page of the tests, its markup is [PRODUCT_HTML] and render_component() writes
+/// the count.
+#[wicket_page("tests/resources/html/test_util")]
+pub(crate) struct ProductPage {
+    pub(crate) count: u32,
+}
+impl FromPageParameters for ProductPage {
+    fn from_page_params(_page_params: Option<PageParameters>) -> Box<dyn WebPage> {
+        Box::new(Self { count: 0 })
+    }
+}
+#[async_trait]
+impl MarkupContainer for ProductPage {
+    fn render_component(
+        &self,
+        _id: ComponentId,
+        response: &mut Response,
+    ) -> std::io::Result<RedirectAction> {
+        response.write_str(&self.count.to_string())?;
+        Ok(RedirectAction::None)
+    }
+
+    async fn invoke_listener(
+        &mut self,
+        component_path: &str,
+        listener: &str,
+        cycle: &mut RequestCycle,
+    ) -> std::io::Result<RedirectAction> {
+        match (component_path, listener) {
+            ("form:link", "click") => Ok(RedirectAction::None),
+            ("form:save", "submit") => Ok(RedirectAction::RedirectSelf),
+            ("form:next", "click") => Ok(RedirectAction::Redirect(Box::new(ProductPage {
+                count: self.count + 1,
+            }))),
+            ("form:external", "click") => {
+                let params = cycle.request.get_query_parameters();
+                let to = params.get_str("to").unwrap_or_default();
+                Ok(RedirectAction::RedirectUrl(to.to_string()))
+            }
+            ("form:fail", "click") => panic!("Listener failed."),
+            ("form:login", "submit") => {
+                let user = cycle.request.get_query_parameters().get_or("user", 0);
+                let user = load_user(user).await;
+                cycle.get_or_create_session().insert(user);
+                cycle.replace_session();
+                Ok(RedirectAction::None)
+            }
+            ("form:logout", "submit") => {
+                cycle.invalidate_session();
+                Ok(RedirectAction::None)
+            }
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+impl WebPage for ProductPage {}
+
+/// The session attribute stored by the login listener of [ProductPage].
+#[derive(Debug, PartialEq)]
+pub(crate) struct CurrentUser(pub(crate) u32);
+
+// A database lookup awaited by the login listener.
+async fn load_user(id: u32) -> CurrentUser {
+    tokio::task::yield_now().await;
+    CurrentUser(id)
+}
+
+/// The application with [ProductPage] mounted at "/product/${id}".
+pub(crate) fn product_app(app: WebApplication) -> Arc<WebApplication> {
+    let mut app = app;
+    app.mount_page::<ProductPage>("/product/${id}");
+    Arc::new(app)
+}
+
+/// A GET request of the uri, with the cookie of the session.
+pub(crate) fn request(uri: &str, session_id: Option<SessionId>) -> Request {
+    request_with(http::Request::builder().uri(uri), session_id)
+}
+
+/// The request of the builder, for other methods and headers, with the cookie of the
+/// session.
+pub(crate) fn request_with(
+    builder: http::request::Builder,
+    session_id: Option<SessionId>,
+) -> Request {
+    let mut builder = builder;
+    if let Some(session_id) = session_id {
+        builder = builder.header(http::header::COOKIE, format!("SESSION_ID={}", session_id));
+    }
+    let (parts, _) = builder.body(()).unwrap().into_parts();
+    Request::new(parts, RequestBody::None)
+}
+
+/// The buffered body of the response.
+pub(crate) fn body(mut response: Response) -> String {
+    match response.take_body() {
+        ResponseBody::Buffered(buf) => String::from_utf8(buf).unwrap(),
+        _ => panic!("Not a buffered response???"),
+    }
+}
+
+/// The session id of the SESSION_ID cookie set by the response.
+pub(crate) fn session_cookie(response: &Response) -> Option<SessionId> {
+    response
+        .get_header("Set-Cookie")?
+        .strip_prefix("SESSION_ID=")
+        .and_then(|cookie| cookie.split(';').next())
+        .and_then(SessionId::parse)
+}
@@ -0,0 +1,93 @@
+use std::io::{Error, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use tokio::sync::mpsc;
+
+/// Chunk size read from a streaming response body.
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Chunks buffered ahead of the socket. Keeps memory flat for large files.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// The hyper body of a wicket response.
+pub struct WicketBody {
+    kind: Kind,
+}
+
+enum Kind {
+    Full(Option<Bytes>),
+    Streaming(mpsc::Receiver<Result<Bytes, Error>>),
+}
+
+impl WicketBody {
+    /// A body of the bytes.
+    pub fn full(bytes: impl Into<Bytes>) -> Self {
+        let bytes = bytes.into();
+        Self {
+            kind: Kind::Full((!bytes.is_empty()).then_some(bytes)),
+        }
+    }
+
+    /// A body without content.
+    pub fn empty() -> Self {
+        Self {
+            kind: Kind::Full(None),
+        }
+    }
+
+    /// Stream the reader to the client in chunks without buffering the whole body.
+    /// The blocking reads are performed on the tokio blocking pool and a full channel
+    /// suspends reading until the client catches up.
+    pub fn from_read(mut read: Box<dyn Read + Send>) -> Self {
+        let (tx, rx) = mpsc::channel(CHUNKS_IN_FLIGHT);
+        tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let chunk = match read.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => Ok(Bytes::copy_from_slice(&buf[..n])),
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = chunk.is_err();
+                // A send error means the client has gone, stop reading.
+                if tx.blocking_send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self {
+            kind: Kind::Streaming(rx),
+        }
+    }
+}
+
+impl Body for WicketBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match &mut self.get_mut().kind {
+            Kind::Full(bytes) => Poll::Ready(bytes.take().map(|b| Ok(Frame::data(b)))),
+            Kind::Streaming(rx) => rx
+                .poll_recv(cx)
+                .map(|chunk| chunk.map(|result| result.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        matches!(self.kind, Kind::Full(None))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Full(bytes) => SizeHint::with_exact(bytes.as_ref().map_or(0, |b| b.len() as u64)),
+            Kind::Streaming(_) => SizeHint::default(),
+        }
+    }
+}
@@ -1,23 +1,46 @@
-use bytes::Bytes;
-use http_body_util::{BodyExt, Full};
+use crate::body::WicketBody;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use hyper::body::Body;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{HeaderMap, StatusCode};
+use hyper_util::rt::TokioIo;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
 use wicket_core::{
     protocol::http::WebApplication,
-    request::{Request, RequestBody, Response},
+    request::{Request, RequestBody, Response, ResponseBody},
 };
 
+// The pause before accepting again after a failed accept.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
 pub async fn handle_hyper_connection(
     app: Arc<WebApplication>,
     hyper_req: hyper::Request<hyper::body::Incoming>,
-) -> Result<hyper::Response<Full<Bytes>>, std::io::Error> {
+) -> Result<hyper::Response<WicketBody>, std::io::Error> {
     // 1. Conversion (Consuming Hyper Request)
     let (parts, incoming_body) = hyper_req.into_parts();
 
     let body_bytes = if parts.method == hyper::Method::GET {
         RequestBody::None
     } else {
-        let collected = incoming_body.collect().await.unwrap();
-        RequestBody::Bytes(collected.to_bytes())
+        // The body is buffered up to the request size limit of the form parameters.
+        let limit = app.multipart_settings.max_request_size;
+        if incoming_body.size_hint().lower() > limit {
+            return payload_too_large();
+        }
+        let limited = Limited::new(incoming_body, usize::try_from(limit).unwrap_or(usize::MAX));
+        match limited.collect().await {
+            Ok(collected) => RequestBody::Bytes(collected.to_bytes()),
+            Err(e) if e.is::<LengthLimitError>() => return payload_too_large(),
+            Err(e) => return Err(Error::other(e)),
+        }
     };
 
     let request = Request::new(parts, body_bytes);
@@ -26,9 +49,399 @@ pub async fn handle_hyper_connection(
     let response = app.process_request(request).await;
 
     // 3. Convert WicketResponse back to Hyper
-    Ok(to_hyper_response(response?))
+    to_hyper_response(response?)
+}
+
+/// 413 for a body larger than the MultipartSettings max_request_size.
+fn payload_too_large() -> Result<hyper::Response<WicketBody>, Error> {
+    let mut response = Response::new();
+    response.status = StatusCode::PAYLOAD_TOO_LARGE.as_u16();
+    response.set_body(ResponseBody::Empty);
+    to_hyper_response(response)
 }
 
-pub fn to_hyper_response(_res: Response) -> hyper::Response<Full<Bytes>> {
-    todo!()
+/// Convert the wicket response into a hyper response.
+/// The status, content type, headers and body are all carried across. Streaming bodies
+/// are sent chunked unless the handler set a Content-Length.
+pub fn to_hyper_response(mut res: Response) -> Result<hyper::Response<WicketBody>, Error> {
+    let status =
+        StatusCode::from_u16(res.status).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(response_headers) = res.get_headers() {
+        for (name, value) in response_headers {
+            let name = hyper::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            let value = hyper::header::HeaderValue::from_str(value)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            headers.append(name, value);
+        }
+    }
+    if let Some(content_type) = res.get_content_type() {
+        let value = hyper::header::HeaderValue::from_str(content_type)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        headers.insert(hyper::header::CONTENT_TYPE, value);
+    }
+
+    let body = match res.take_body() {
+        ResponseBody::Buffered(buf) => WicketBody::full(buf),
+        ResponseBody::Streaming(read) => WicketBody::from_read(read),
+        ResponseBody::Empty => WicketBody::empty(),
+    };
+
+    let mut hyper_response = hyper::Response::new(body);
+    *hyper_response.status_mut() = status;
+    *hyper_response.headers_mut() = headers;
+    Ok(hyper_response)
+}
+
+/// Serve a WebApplication over HTTP/1.1.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use wicket_core::protocol::http::WebApplication;
+/// # use wicket_hyper::server::WicketServer;
+/// # async fn run(app: Arc<WebApplication>) -> std::io::Result<()> {
+/// WicketServer::builder(app)
+///     .addr(([127, 0, 0, 1], 8080))
+///     .bind()
+///     .await?
+///     .serve()
+///     .await
+/// # }
+/// ```
+pub struct WicketServer {
+    app: Arc<WebApplication>,
+    listener: TcpListener,
+    keep_alive: bool,
+}
+
+/// Build a WicketServer, see [WicketServer::builder].
+pub struct WicketServerBuilder {
+    app: Arc<WebApplication>,
+    addr: SocketAddr,
+    keep_alive: bool,
+}
+
+impl WicketServerBuilder {
+    /// The socket address to listen on, defaults to 127.0.0.1:8080.
+    pub fn addr(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    /// Enable HTTP/1.1 keep-alive, defaults to true.
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Bind the listener. Use port 0 for an OS assigned port, see [WicketServer::local_addr].
+    pub async fn bind(self) -> Result<WicketServer, Error> {
+        let listener = TcpListener::bind(self.addr).await?;
+        Ok(WicketServer {
+            app: self.app,
+            listener,
+            keep_alive: self.keep_alive,
+        })
+    }
+}
+
+impl WicketServer {
+    /// A builder serving the application, see [WicketServerBuilder::bind].
+    pub fn builder(app: Arc<WebApplication>) -> WicketServerBuilder {
+        WicketServerBuilder {
+            app,
+            addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            keep_alive: true,
+        }
+    }
+
+    /// The address the server is bound to eg the OS assigned port.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    /// Serve until ctrl-c is received.
+    pub async fn serve(self) -> Result<(), Error> {
+        self.serve_with_shutdown(async {
+            // An error installing the handler leaves the server running until killed.
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+        .await
+    }
+
+    /// Serve until the signal completes. The listener is closed at once and in-flight
+    /// connections are allowed to complete before returning.
+    ///
+    /// Each connection is a task of the runtime, on a multi-threaded runtime the
+    /// requests of different sessions are handled in parallel.
+    pub async fn serve_with_shutdown<F>(self, signal: F) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        let graceful = GracefulShutdown::new();
+        let mut signal = std::pin::pin!(signal);
+        let sweeper = self.app.spawn_session_sweeper();
+
+        loop {
+            let (stream, _remote) = tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok(conn) => conn,
+                    // Per connection failures (eg EMFILE, ECONNABORTED) do not stop the server.
+                    // Running out of file descriptors fails again at once, back off
+                    // rather than spin.
+                    Err(_) => {
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                },
+                _ = &mut signal => break,
+            };
+
+            let app = self.app.clone();
+            let service = service_fn(move |req| handle_hyper_connection(app.clone(), req));
+            let conn = http1::Builder::new()
+                .keep_alive(self.keep_alive)
+                .serve_connection(TokioIo::new(stream), service);
+            let conn = graceful.watch(conn);
+            tokio::spawn(async move {
+                // The client has gone, there is no one to report the error to.
+                let _ = conn.await;
+            });
+        }
+
+        drop(self.listener);
+        graceful.shutdown().await;
+        sweeper.abort();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::sync::RwLock;
+
+    use http_body_util::BodyExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use wicket_core::async_trait;
+    use wicket_core::components::WebPage;
+    use wicket_core::request::cycle::{HandlerResult, RequestCycle};
+    use wicket_core::request::handler::PageProvider;
+    use wicket_core::request::{
+        RequestHandler, RequestMapper, RequestMapperLogic, RequestMappingResult,
+    };
+    use wicket_request::request::post::multipart::MultipartSettings;
+    use wicket_request::request::url::Url;
+
+    use super::*;
+
+    struct HelloHandler {}
+    #[async_trait]
+    impl RequestHandler for HelloHandler {
+        async fn respond(&self, cycle: &mut RequestCycle) -> std::io::Result<HandlerResult> {
+            cycle.response.set_content_type("text/plain");
+            if cycle.request.parts.uri.path() == "/stream" {
+                let body = std::io::Cursor::new(vec![b'x'; 100_000]);
+                cycle
+                    .response
+                    .set_body(ResponseBody::Streaming(Box::new(body)));
+            } else {
+                cycle.response.set_body(ResponseBody::Buffered(vec![]));
+                cycle.response.write_all(b"Hello")?;
+            }
+            Ok(HandlerResult::Complete)
+        }
+
+        fn get_response_page(&self) -> &Option<Box<dyn WebPage>> {
+            &None
+        }
+
+        fn as_page_provider(&self) -> &Option<PageProvider> {
+            &None
+        }
+    }
+
+    struct HelloMapper {}
+    impl RequestMapperLogic for HelloMapper {
+        fn map_request(&self, _request: &Request) -> Option<RequestMappingResult> {
+            Some(RequestMappingResult {
+                handler: Box::new(HelloHandler {}),
+                compatibility_score: 1,
+            })
+        }
+
+        fn map_handler(&self, _handler: &dyn RequestHandler) -> Option<Url> {
+            None
+        }
+    }
+
+    fn hello_app() -> Arc<WebApplication> {
+        Arc::new(WebApplication {
+            app_request_mappers: RwLock::new(vec![RequestMapper::Custom(Box::new(HelloMapper {}))]),
+            multipart_settings: MultipartSettings {
+                max_request_size: 1000,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn to_hyper_response_test() {
+        let mut response = Response::new();
+        response.status = 201;
+        response.set_content_type("text/plain");
+        response.set_header("X-Test", "abc");
+        response.add_header("Set-Cookie", "a=1");
+        response.add_header("Set-Cookie", "b=2");
+        response.set_body(ResponseBody::Buffered(b"created".to_vec()));
+
+        let hyper_response = to_hyper_response(response).unwrap();
+        assert_eq!(StatusCode::CREATED, hyper_response.status());
+        let headers: HashMap<_, _> = hyper_response
+            .headers()
+            .iter()
+            .map(|(k, v)| (k.as_str().to_owned(), v.to_str().unwrap().to_owned()))
+            .collect();
+        assert_eq!("text/plain", headers["content-type"]);
+        assert_eq!("abc", headers["x-test"]);
+        let cookies: Vec<_> = hyper_response
+            .headers()
+            .get_all(hyper::header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        assert_eq!(vec!["a=1", "b=2"], cookies);
+        let body = hyper_response.into_body().collect().await.unwrap();
+        assert_eq!(b"created".as_slice(), &body.to_bytes()[..]);
+
+        let mut response = Response::new();
+        response.set_body(ResponseBody::Streaming(Box::new(&b"streamed"[..])));
+        let body = to_hyper_response(response)
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(b"streamed".as_slice(), &body.to_bytes()[..]);
+
+        let mut response = Response::new();
+        response.status = 99;
+        assert!(to_hyper_response(response).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn serve_with_shutdown_test() {
+        let server = WicketServer::builder(hello_app())
+            .addr(([127, 0, 0, 1], 0))
+            .bind()
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let client = async move {
+            let raw = get(addr, "/").await;
+            let streamed = get(addr, "/stream").await;
+            shutdown_tx.send(()).unwrap();
+            (raw, streamed)
+        };
+        let serve = server.serve_with_shutdown(async {
+            shutdown_rx.await.ok();
+        });
+
+        let (served, (raw, streamed)) = tokio::join!(serve, client);
+        served.unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200 OK"), "{}", raw);
+        assert!(raw.contains("content-type: text/plain"), "{}", raw);
+        assert!(raw.ends_with("Hello"), "{}", raw);
+
+        assert!(
+            streamed.contains("transfer-encoding: chunked"),
+            "{}",
+            streamed
+        );
+        let body = streamed.split_once("\r\n\r\n").unwrap().1;
+        assert_eq!(100_000, body.bytes().filter(|b| *b == b'x').count());
+    }
+
+    #[tokio::test]
+    async fn request_size_limit_test() {
+        let server = WicketServer::builder(hello_app())
+            .addr(([127, 0, 0, 1], 0))
+            .bind()
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let client = async move {
+            let post = |headers: &str, body: &[u8]| {
+                let mut request = format!(
+                    "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+                    headers
+                )
+                .into_bytes();
+                request.extend_from_slice(body);
+                request
+            };
+            let small = send(addr, &post("Content-Length: 10\r\n", &[b'a'; 10])).await;
+            // Rejected on the declared length, before the body is read.
+            let declared = send(addr, &post("Content-Length: 2000\r\n", &[b'a'; 2000])).await;
+            // Rejected once the chunks exceed the limit.
+            let mut chunked_body = Vec::new();
+            for _ in 0..3 {
+                chunked_body.extend_from_slice(b"1f4\r\n");
+                chunked_body.extend_from_slice(&[b'a'; 500]);
+                chunked_body.extend_from_slice(b"\r\n");
+            }
+            chunked_body.extend_from_slice(b"0\r\n\r\n");
+            let chunked = send(addr, &post("Transfer-Encoding: chunked\r\n", &chunked_body)).await;
+            shutdown_tx.send(()).unwrap();
+            (small, declared, chunked)
+        };
+        let serve = server.serve_with_shutdown(async {
+            shutdown_rx.await.ok();
+        });
+
+        let (served, (small, declared, chunked)) = tokio::join!(serve, client);
+        served.unwrap();
+        assert!(small.starts_with("HTTP/1.1 200 OK"), "{}", small);
+        assert!(
+            declared.starts_with("HTTP/1.1 413 Payload Too Large"),
+            "{}",
+            declared
+        );
+        assert!(
+            chunked.starts_with("HTTP/1.1 413 Payload Too Large"),
+            "{}",
+            chunked
+        );
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        send(addr, request.as_bytes()).await
+    }
+
+    async fn send(addr: SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        // The server may reset the connection after answering without reading the body.
+        let mut raw = Vec::new();
+        let mut buf = [0; 4096];
+        while let Ok(n @ 1..) = stream.read(&mut buf).await {
+            raw.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(raw).unwrap()
+    }
 }
@@ -125,11 +125,18 @@ pub fn wicket_page(attribs: TokenStream, item: TokenStream) -> TokenStream {
          }
      }
 
+     impl #crate_root::components::StaticMarkupIdentifier for #name {
+         fn markup_type() -> &'static #crate_root::components::MarkupType {
+             &#const_name
+         }
+     }
+
     #html_data
 
     inventory::submit! {
         #crate_root::session::page_factory::PageEntry {
             id: &#const_name,
+            module_path: module_path!(),
             constructor: |params| {
                 #crate_root::session::page_factory::PageEntry::from_page_params::<#name>(params)
             }
@@ -143,25 +143,8 @@ fn generate_codegen(component_name: &syn::Ident, markups: &mut [DiscoveredMarkup
         #static_array_declaration
 
         impl #crate_root::components::MarkupLookup for #component_name {
-            fn lookup_markup(
-                &self,
-                style: Option<u8>,
-                variation: Option<u8>,
-                lang: Option<u8>,
-                country: Option<u8>
-            ) -> Option<&#crate_root::markup::MarkupResource> {
-
-                // The list is pre-sorted by specificity at compile time,
-                // so the first match via standard iteration find() is guaranteed to be
-                // the correct Wicket dimension fallback.
-                #markup_resource_vec_name.iter().find(|r| {
-                    // Match style (if provided, must match; if None, must be None)
-                    if r.style != style { return false; }
-                    if r.variation != variation { return false; }
-                    if r.lang != lang { return false; }
-                    if r.country != country { return false; }
-                    true
-                })
+            fn markup_resources(&self) -> &[#crate_root::markup::MarkupResource] {
+                &#markup_resource_vec_name[..]
             }
         }
     }
@@ -1 +1,3 @@
 pub mod mapper;
+pub mod post;
+pub mod url;
@@ -0,0 +1,190 @@
+//! The page instance part of a url eg "/product/5?3-1.click-form-submit".
+//!
+//! The info is a value-less query parameter of the form
+//! `<pageId>[-<renderCount>][.<listener>-<componentPath>]`. Component paths are held
+//! with ':' separators and encoded with '-', a '-' within a component id is encoded as '~'.
+use std::fmt::Display;
+
+use crate::request::url::{QueryParameter, Url};
+
+const PAGE_SEPARATOR: char = '-';
+const COMPONENT_INFO_SEPARATOR: char = '.';
+const COMPONENT_SEPARATOR: char = ':';
+const SEPARATOR_ENCODED: char = '~';
+
+/// Identifies a page instance, and optionally a component listener of that instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageComponentInfo {
+    pub page_id: u16,
+    /// The version of the page instance, None for the most recent.
+    pub render_count: Option<u16>,
+    pub component_info: Option<ComponentInfo>,
+}
+
+/// The listener to invoke on a component of a page instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentInfo {
+    pub listener: String,
+    /// The ':' separated component ids from the page eg "form:submit".
+    pub component_path: String,
+}
+
+impl PageComponentInfo {
+    /// The info of the page version, without a component listener.
+    pub fn new(page_id: u16, render_count: Option<u16>) -> Self {
+        Self {
+            page_id,
+            render_count,
+            component_info: None,
+        }
+    }
+
+    /// Parse the info eg "3", "3-1" or "3-1.click-form-submit".
+    pub fn parse(value: &str) -> Option<Self> {
+        let (page_info, component_info) = match value.split_once(COMPONENT_INFO_SEPARATOR) {
+            Some((page_info, component_info)) => (page_info, Some(component_info)),
+            None => (value, None),
+        };
+
+        let (page_id, render_count) = match page_info.split_once(PAGE_SEPARATOR) {
+            Some((page_id, render_count)) => (page_id, Some(parse_number(render_count)?)),
+            None => (page_info, None),
+        };
+
+        let component_info = match component_info {
+            Some(info) => {
+                let (listener, path) = info.split_once(PAGE_SEPARATOR)?;
+                if path.is_empty() {
+                    return None;
+                }
+                Some(ComponentInfo {
+                    listener: listener.to_string(),
+                    component_path: decode_component_path(path),
+                })
+            }
+            None => None,
+        };
+
+        Some(Self {
+            page_id: parse_number(page_id)?,
+            render_count,
+            component_info,
+        })
+    }
+
+    /// Remove the info from the url query parameters, return it if present.
+    pub fn take_from_url(url: &mut Url) -> Option<Self> {
+        let index = url
+            .query_parameters
+            .iter()
+            .position(|qp| qp.value.is_empty() && Self::parse(&qp.name).is_some())?;
+        let param = url.query_parameters.remove(index);
+        Self::parse(&param.name)
+    }
+
+    /// Add the info as the first query parameter of the url.
+    pub fn insert_into(&self, url: &mut Url) {
+        url.query_parameters.insert(
+            0,
+            QueryParameter {
+                name: self.to_string(),
+                value: String::new(),
+            },
+        );
+    }
+}
+
+// Only plain digits, "+1" or " 1" are not a page id.
+fn parse_number(value: &str) -> Option<u16> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn encode_component_path(path: &str) -> String {
+    path.chars()
+        .map(|c| match c {
+            PAGE_SEPARATOR => SEPARATOR_ENCODED,
+            COMPONENT_SEPARATOR => PAGE_SEPARATOR,
+            c => c,
+        })
+        .collect()
+}
+
+fn decode_component_path(path: &str) -> String {
+    path.chars()
+        .map(|c| match c {
+            SEPARATOR_ENCODED => PAGE_SEPARATOR,
+            PAGE_SEPARATOR => COMPONENT_SEPARATOR,
+            c => c,
+        })
+        .collect()
+}
+
+impl Display for PageComponentInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.page_id)?;
+        if let Some(render_count) = self.render_count {
+            write!(f, "{}{}", PAGE_SEPARATOR, render_count)?;
+        }
+        if let Some(info) = &self.component_info {
+            write!(
+                f,
+                "{}{}{}{}",
+                COMPONENT_INFO_SEPARATOR,
+                info.listener,
+                PAGE_SEPARATOR,
+                encode_component_path(&info.component_path)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ComponentInfo, PageComponentInfo};
+    use crate::request::url::Url;
+
+    #[test]
+    pub fn parse_test() {
+        assert_eq!(
+            Some(PageComponentInfo::new(3, None)),
+            PageComponentInfo::parse("3")
+        );
+        assert_eq!(
+            Some(PageComponentInfo::new(3, Some(1))),
+            PageComponentInfo::parse("3-1")
+        );
+        let info = PageComponentInfo::parse("3-1.click-form-my~link").unwrap();
+        assert_eq!(
+            Some(ComponentInfo {
+                listener: "click".to_string(),
+                component_path: "form:my-link".to_string()
+            }),
+            info.component_info
+        );
+        assert_eq!("3-1.click-form-my~link", info.to_string());
+
+        for invalid in ["", "x", "3-", "-1", "3-x", "3-1.click", "3-1.click-", "+3"] {
+            assert_eq!(None, PageComponentInfo::parse(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    pub fn url_test() {
+        let mut url = Url::parse("/product/5?ref=home&3-1");
+        let info = PageComponentInfo::take_from_url(&mut url).unwrap();
+        assert_eq!(PageComponentInfo::new(3, Some(1)), info);
+        assert_eq!("/product/5?ref=home", url.to_string());
+
+        info.insert_into(&mut url);
+        assert_eq!("/product/5?3-1&ref=home", url.to_string());
+
+        // A named parameter with a numeric value is not page info.
+        let mut url = Url::parse("/product?id=3");
+        assert_eq!(None, PageComponentInfo::take_from_url(&mut url));
+        assert_eq!(1, url.query_parameters.len());
+    }
+}
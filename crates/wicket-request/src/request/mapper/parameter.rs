@@ -0,0 +1,277 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::request::url::{QueryParameter, Url};
+
+/// The parameters a page is constructed with.
+///
+/// Named parameters come from the query string and the `${}`/`#{}` segments of a mount,
+/// keys are case insensitive and may repeat eg "?tag=a&tag=b". Indexed parameters are
+/// the url segments following the page name of a bookmarkable or package url eg
+/// "/wicket/bookmarkable/BlogPage/2024/05".
+#[derive(Default, Clone)]
+pub struct PageParameters {
+    pub named_parameters: Vec<NamedPair>,
+    pub indexed_parameters: Vec<String>,
+}
+
+/// Failure to read a typed page parameter.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParameterError {
+    #[error("Missing page parameter '{0}'")]
+    Missing(String),
+    #[error("Page parameter '{key}' value '{value}' is not a valid {type_name}")]
+    Invalid {
+        key: String,
+        value: String,
+        type_name: &'static str,
+    },
+}
+
+impl PageParameters {
+    /// Empty parameters.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse a query string without the leading '?', values are percent decoded.
+    pub fn parse_query(query: &str) -> Self {
+        let mut params = Self::new();
+        params.named_parameters = form_urlencoded::parse(query.as_bytes())
+            .map(|(key, value)| NamedPair {
+                key: key.into_owned(),
+                value: value.into_owned(),
+                value_type: ValueType::QueryString,
+            })
+            .collect();
+        params
+    }
+
+    /// The query parameters of the url.
+    pub fn from_url(url: &Url) -> Self {
+        let mut params = Self::new();
+        params.add_query_parameters(url);
+        params
+    }
+
+    /// Append the url query parameters as ValueType::QueryString.
+    pub fn add_query_parameters(&mut self, url: &Url) {
+        self.named_parameters
+            .extend(url.query_parameters.iter().map(|qp| NamedPair {
+                key: qp.name.clone(),
+                value: qp.value.clone(),
+                value_type: ValueType::QueryString,
+            }));
+    }
+
+    /// Add a value to the key, a key can have several values.
+    pub fn add(mut self, key: String, value: String) -> Self {
+        self.named_parameters.push(NamedPair {
+            key,
+            value,
+            value_type: ValueType::Manual,
+        });
+        self
+    }
+
+    /// Replace all the values of the key.
+    pub fn set(mut self, key: String, value: String) -> Self {
+        self.remove(&key);
+        self.add(key, value)
+    }
+
+    /// Remove all the values of the key.
+    pub fn remove(&mut self, key: &str) {
+        self.named_parameters
+            .retain(|param| !param.key.eq_ignore_ascii_case(key));
+    }
+
+    /// Add an indexed parameter, encoded as a path segment.
+    pub fn add_indexed(mut self, value: String) -> Self {
+        self.indexed_parameters.push(value);
+        self
+    }
+
+    /// The indexed parameter at the index.
+    pub fn get_indexed(&self, index: usize) -> Option<&str> {
+        self.indexed_parameters.get(index).map(String::as_str)
+    }
+
+    /// The first value of the key.
+    pub fn get(&self, value_name: &str) -> Option<&NamedPair> {
+        self.named_parameters
+            .iter()
+            .find(|param| param.key.eq_ignore_ascii_case(value_name))
+    }
+
+    /// All the values of a multi valued key in url order.
+    pub fn get_all<'a>(&'a self, value_name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.named_parameters
+            .iter()
+            .filter(move |param| param.key.eq_ignore_ascii_case(value_name))
+            .map(|param| param.value.as_str())
+    }
+
+    /// The first value of the key as text.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).map(|param| param.value.as_str())
+    }
+
+    /// Parse the first value of the key.
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Result<T, ParameterError> {
+        let value = self
+            .get_str(key)
+            .ok_or_else(|| ParameterError::Missing(key.to_string()))?;
+        value.trim().parse().map_err(|_| ParameterError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            type_name: std::any::type_name::<T>(),
+        })
+    }
+
+    /// Parse the first value of the key, the default when missing or invalid.
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.get_as(key).unwrap_or(default)
+    }
+
+    /// Parse the first value of the key as an i64.
+    pub fn get_i64(&self, key: &str) -> Result<i64, ParameterError> {
+        self.get_as(key)
+    }
+
+    /// Parse the first value of the key as a u64.
+    pub fn get_u64(&self, key: &str) -> Result<u64, ParameterError> {
+        self.get_as(key)
+    }
+
+    /// Parse the first value of the key as an i32.
+    pub fn get_i32(&self, key: &str) -> Result<i32, ParameterError> {
+        self.get_as(key)
+    }
+
+    /// Parse the first value of the key as an f64.
+    pub fn get_f64(&self, key: &str) -> Result<f64, ParameterError> {
+        self.get_as(key)
+    }
+
+    /// Accepts true/false, yes/no, on/off and 1/0, case insensitive. A checkbox
+    /// submits "on".
+    pub fn get_bool(&self, key: &str) -> Result<bool, ParameterError> {
+        let value = self
+            .get_str(key)
+            .ok_or_else(|| ParameterError::Missing(key.to_string()))?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => Err(ParameterError::Invalid {
+                key: key.to_string(),
+                value: value.to_string(),
+                type_name: "bool",
+            }),
+        }
+    }
+
+    /// True without indexed and named parameters.
+    pub fn is_empty(&self) -> bool {
+        self.named_parameters.is_empty() && self.indexed_parameters.is_empty()
+    }
+
+    /// Encode into the url, the indexed parameters as path segments and the named
+    /// parameters as the query string.
+    pub fn encode_into(&self, url: &mut Url) {
+        url.segments.extend(self.indexed_parameters.iter().cloned());
+        for param in &self.named_parameters {
+            url.query_parameters.push(QueryParameter {
+                name: param.key.clone(),
+                value: param.value.clone(),
+            });
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    // The named parameter is set manually in the application code.
+    Manual,
+    // The named parameter is read/parsed from the query string.
+    QueryString,
+    // The named parameter is read/parsed from the url path.
+    Path,
+}
+
+#[derive(Clone)]
+pub struct NamedPair {
+    pub key: String,
+    pub value: String,
+    pub value_type: ValueType,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageParameters, ParameterError, ValueType};
+    use crate::request::url::Url;
+
+    #[test]
+    pub fn parse_query_test() {
+        let params = PageParameters::parse_query("id=42&tag=a%20b&TAG=c&flag=on&q=x+y");
+        assert_eq!(Some("42"), params.get_str("id"));
+        assert_eq!(vec!["a b", "c"], params.get_all("tag").collect::<Vec<_>>());
+        assert_eq!(Some("x y"), params.get_str("q"));
+        assert_eq!(ValueType::QueryString, params.get("id").unwrap().value_type);
+
+        let params = PageParameters::from_url(&Url::parse("/page?a=1&a=2"));
+        assert_eq!(2, params.get_all("a").count());
+    }
+
+    #[test]
+    pub fn typed_getter_test() {
+        let params = PageParameters::parse_query("id=42&neg=-7&flag=on&off=No&ratio=0.5&bad=x");
+        assert_eq!(Ok(42), params.get_i64("id"));
+        assert_eq!(Ok(42), params.get_u64("id"));
+        assert_eq!(Ok(-7), params.get_i32("neg"));
+        assert_eq!(Ok(0.5), params.get_f64("ratio"));
+        assert_eq!(Ok(true), params.get_bool("flag"));
+        assert_eq!(Ok(false), params.get_bool("off"));
+        assert_eq!(
+            Err(ParameterError::Missing("page".to_string())),
+            params.get_i64("page")
+        );
+        assert!(matches!(
+            params.get_u64("neg"),
+            Err(ParameterError::Invalid { .. })
+        ));
+        assert!(matches!(
+            params.get_bool("bad"),
+            Err(ParameterError::Invalid { .. })
+        ));
+        assert_eq!(10, params.get_or("page", 10));
+        assert_eq!(10, params.get_or("bad", 10));
+        assert_eq!(42u16, params.get_or("id", 10));
+    }
+
+    #[test]
+    pub fn multi_value_test() {
+        let mut params = PageParameters::new()
+            .add("tag".to_string(), "a".to_string())
+            .add("tag".to_string(), "b".to_string())
+            .set("id".to_string(), "1".to_string())
+            .set("ID".to_string(), "2".to_string());
+        assert_eq!(vec!["a", "b"], params.get_all("tag").collect::<Vec<_>>());
+        assert_eq!(vec!["2"], params.get_all("id").collect::<Vec<_>>());
+        params.remove("TAG");
+        assert_eq!(None, params.get_str("tag"));
+    }
+
+    #[test]
+    pub fn encode_into_test() {
+        let params = PageParameters::new()
+            .add_indexed("2024".to_string())
+            .add_indexed("a/b".to_string())
+            .add("q".to_string(), "x&y".to_string());
+        assert_eq!(Some("a/b"), params.get_indexed(1));
+        let mut url = Url::new().add_segment("blog");
+        params.encode_into(&mut url);
+        assert_eq!("/blog/2024/a%2Fb?q=x%26y", url.to_string());
+    }
+}
@@ -0,0 +1,108 @@
+pub mod multipart;
+
+use multipart::FileUpload;
+
+/// The form parameters of a POST body, see [PostParameters::parse_urlencoded] and
+/// [multipart::parse]. Parameter names are case sensitive and may repeat eg the values
+/// of a multiple select.
+#[derive(Default)]
+pub struct PostParameters {
+    parameters: Vec<(String, String)>,
+    files: Vec<FileUpload>,
+}
+
+impl PostParameters {
+    /// Empty post parameters.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body, values are percent decoded.
+    pub fn parse_urlencoded(body: &[u8]) -> Self {
+        let mut params = Self::new();
+        params.parameters = form_urlencoded::parse(body)
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        params
+    }
+
+    /// Add a form field value.
+    pub fn add_parameter(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.parameters.push((name.into(), value.into()));
+    }
+
+    /// Add an uploaded file.
+    pub fn add_file(&mut self, file: FileUpload) {
+        self.files.push(file);
+    }
+
+    /// The distinct parameter names in body order.
+    pub fn get_parameter_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in &self.parameters {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The first value of the parameter.
+    pub fn get_parameter_value(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All the values of a multi valued parameter in body order.
+    pub fn get_parameter_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.parameters
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first file uploaded with the field name.
+    pub fn get_file(&self, field_name: &str) -> Option<&FileUpload> {
+        self.files.iter().find(|file| file.field_name == field_name)
+    }
+
+    /// All the files uploaded with the field name eg `<input type="file" multiple>`.
+    pub fn get_files<'a>(&'a self, field_name: &'a str) -> impl Iterator<Item = &'a FileUpload> {
+        self.files
+            .iter()
+            .filter(move |file| file.field_name == field_name)
+    }
+
+    /// Take ownership of the uploaded files, eg to persist them.
+    pub fn take_files(&mut self) -> Vec<FileUpload> {
+        std::mem::take(&mut self.files)
+    }
+
+    /// True without form fields and files.
+    pub fn is_empty(&self) -> bool {
+        self.parameters.is_empty() && self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PostParameters;
+
+    #[test]
+    pub fn parse_urlencoded_test() {
+        let params =
+            PostParameters::parse_urlencoded(b"name=J%C3%BCrgen+M&color=red&color=blue&empty=");
+        assert_eq!(Some("Jürgen M"), params.get_parameter_value("name"));
+        assert_eq!(
+            vec!["red", "blue"],
+            params.get_parameter_values("color").collect::<Vec<_>>()
+        );
+        assert_eq!(Some(""), params.get_parameter_value("empty"));
+        assert_eq!(None, params.get_parameter_value("Name"));
+        assert_eq!(vec!["name", "color", "empty"], params.get_parameter_names());
+
+        assert!(PostParameters::parse_urlencoded(b"").is_empty());
+    }
+}
@@ -0,0 +1,551 @@
+//! A streaming `multipart/form-data` parser (RFC 7578).
+//!
+//! The body is read in chunks, text fields are kept in memory and file parts are written
+//! to temporary files as they arrive so an upload is never held in memory. The temporary
+//! files are deleted when the [FileUpload] is dropped unless persisted.
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use super::PostParameters;
+
+const CHUNK_SIZE: usize = 16 * 1024;
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// The upload limits and the directory uploads are spilled to.
+#[derive(Debug, Clone)]
+pub struct MultipartSettings {
+    /// The maximum size of a single uploaded file in bytes.
+    pub max_file_size: u64,
+    /// The maximum size of the whole request body in bytes.
+    pub max_request_size: u64,
+    /// The directory of the temporary files of the uploads.
+    pub temp_dir: PathBuf,
+}
+
+impl Default for MultipartSettings {
+    fn default() -> Self {
+        Self {
+            max_file_size: 10 * 1024 * 1024,
+            max_request_size: 50 * 1024 * 1024,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+/// Why a multipart body could not be parsed.
+#[derive(Debug, Error)]
+pub enum MultipartError {
+    /// An uploaded file exceeds [MultipartSettings::max_file_size].
+    #[error(
+        "The uploaded file '{file_name}' of field '{field_name}' exceeds the limit of {limit} bytes"
+    )]
+    FileTooLarge {
+        field_name: String,
+        file_name: String,
+        limit: u64,
+    },
+    /// The body exceeds [MultipartSettings::max_request_size].
+    #[error("The request body exceeds the limit of {limit} bytes")]
+    RequestTooLarge { limit: u64 },
+    /// The Content-Type header has no boundary parameter.
+    #[error("The multipart content type has no boundary")]
+    MissingBoundary,
+    /// The body does not follow the multipart syntax, with the reason.
+    #[error("Malformed multipart body: {0}")]
+    Malformed(&'static str),
+    /// Reading the body or writing a temporary file failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A file part of the body, held in a temporary file.
+pub struct FileUpload {
+    /// The name of the form field.
+    pub field_name: String,
+    /// The client file name without any directory.
+    pub file_name: String,
+    /// The Content-Type of the part, None when the client sent none.
+    pub content_type: Option<String>,
+    /// The size of the file in bytes.
+    pub size: u64,
+    file: NamedTempFile,
+}
+
+impl FileUpload {
+    /// The path of the temporary file.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Open the temporary file for reading from the start.
+    pub fn open(&self) -> std::io::Result<File> {
+        self.file.reopen()
+    }
+
+    /// Move the upload to the path, it is no longer deleted on drop. The temp_dir must be
+    /// on the same file system as the path.
+    pub fn persist(self, path: impl AsRef<Path>) -> std::io::Result<File> {
+        self.file.persist(path).map_err(|e| e.error)
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type, None for other content types.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = split_header_value(content_type);
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// Parse the body with the boundary of its content type, see [boundary].
+pub fn parse<R: Read>(
+    reader: R,
+    boundary: &str,
+    settings: &MultipartSettings,
+) -> Result<PostParameters, MultipartError> {
+    let mut input = Input {
+        reader,
+        buf: Vec::with_capacity(CHUNK_SIZE),
+        total: 0,
+        limit: settings.max_request_size,
+    };
+    let dash_boundary = format!("--{}", boundary).into_bytes();
+    // The delimiter of the parts, the CRLF belongs to the boundary not the part content.
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut params = PostParameters::new();
+
+    // Skip the preamble.
+    loop {
+        if let Some(i) = find(&input.buf, &dash_boundary) {
+            input.buf.drain(..i + dash_boundary.len());
+            break;
+        }
+        input.keep_tail(dash_boundary.len() - 1);
+        if !input.fill()? {
+            return Err(MultipartError::Malformed("missing the first boundary"));
+        }
+    }
+
+    loop {
+        input.ensure(2)?;
+        if input.buf.starts_with(b"--") {
+            // The close delimiter, the epilogue is ignored.
+            return Ok(params);
+        }
+        if !input.buf.starts_with(b"\r\n") {
+            return Err(MultipartError::Malformed("missing CRLF after a boundary"));
+        }
+        input.buf.drain(..2);
+
+        let part = read_headers(&mut input)?;
+        match part.file_name {
+            Some(file_name) => {
+                let mut sink = FileSink {
+                    file: tempfile::Builder::new()
+                        .prefix("wicket-upload-")
+                        .tempfile_in(&settings.temp_dir)?,
+                    size: 0,
+                    limit: settings.max_file_size,
+                };
+                read_content(&mut input, &delimiter, |chunk| sink.write(chunk)).map_err(
+                    |e| match e {
+                        MultipartError::FileTooLarge { limit, .. } => {
+                            MultipartError::FileTooLarge {
+                                field_name: part.name.clone(),
+                                file_name: file_name.clone(),
+                                limit,
+                            }
+                        }
+                        e => e,
+                    },
+                )?;
+                // An empty file input is sent without a file name.
+                if !file_name.is_empty() {
+                    sink.file.flush()?;
+                    params.add_file(FileUpload {
+                        field_name: part.name,
+                        file_name,
+                        content_type: part.content_type,
+                        size: sink.size,
+                        file: sink.file,
+                    });
+                }
+            }
+            None => {
+                let mut value = Vec::new();
+                read_content(&mut input, &delimiter, |chunk| {
+                    value.extend_from_slice(chunk);
+                    Ok(())
+                })?;
+                params.add_parameter(part.name, String::from_utf8_lossy(&value));
+            }
+        }
+    }
+}
+
+/// The buffered body, counting the bytes read against the request limit.
+struct Input<R> {
+    reader: R,
+    buf: Vec<u8>,
+    total: u64,
+    limit: u64,
+}
+
+impl<R: Read> Input<R> {
+    /// Append the next chunk of the body, false at the end of the body.
+    fn fill(&mut self) -> Result<bool, MultipartError> {
+        let len = self.buf.len();
+        self.buf.resize(len + CHUNK_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(read) => break read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.buf.truncate(len);
+                    return Err(e.into());
+                }
+            }
+        };
+        self.buf.truncate(len + read);
+        self.total += read as u64;
+        if self.total > self.limit {
+            return Err(MultipartError::RequestTooLarge { limit: self.limit });
+        }
+        Ok(read > 0)
+    }
+
+    fn ensure(&mut self, len: usize) -> Result<(), MultipartError> {
+        while self.buf.len() < len {
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of the body"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop all but the last len bytes, they may be the start of a boundary.
+    fn keep_tail(&mut self, len: usize) {
+        if self.buf.len() > len {
+            self.buf.drain(..self.buf.len() - len);
+        }
+    }
+}
+
+struct PartHeaders {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+fn read_headers<R: Read>(input: &mut Input<R>) -> Result<PartHeaders, MultipartError> {
+    let end = loop {
+        if input.buf.starts_with(b"\r\n") {
+            return Err(MultipartError::Malformed("a part without headers"));
+        }
+        if let Some(end) = find(&input.buf, b"\r\n\r\n") {
+            break end;
+        }
+        if input.buf.len() > MAX_HEADER_SIZE {
+            return Err(MultipartError::Malformed("part headers too large"));
+        }
+        if !input.fill()? {
+            return Err(MultipartError::Malformed(
+                "unexpected end of the part headers",
+            ));
+        }
+    };
+    let headers = String::from_utf8_lossy(&input.buf[..end]).into_owned();
+    input.buf.drain(..end + 4);
+
+    let mut name = None;
+    let mut file_name = None;
+    let mut content_type = None;
+    for line in headers.split("\r\n") {
+        let Some((header, value)) = line.split_once(':') else {
+            return Err(MultipartError::Malformed("invalid part header"));
+        };
+        let header = header.trim();
+        if header.eq_ignore_ascii_case("content-disposition") {
+            let (disposition, params) = split_header_value(value);
+            if !disposition.eq_ignore_ascii_case("form-data") {
+                return Err(MultipartError::Malformed("part is not form-data"));
+            }
+            for (param, value) in params {
+                if param.eq_ignore_ascii_case("name") {
+                    name = Some(value);
+                } else if param.eq_ignore_ascii_case("filename") {
+                    file_name = Some(base_name(&value).to_string());
+                }
+            }
+        } else if header.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim().to_string());
+        }
+    }
+
+    Ok(PartHeaders {
+        name: name.ok_or(MultipartError::Malformed("part without a name"))?,
+        file_name,
+        content_type,
+    })
+}
+
+/// Pass the part content to the sink up to the delimiter, which is consumed.
+fn read_content<R: Read>(
+    input: &mut Input<R>,
+    delimiter: &[u8],
+    mut sink: impl FnMut(&[u8]) -> Result<(), MultipartError>,
+) -> Result<(), MultipartError> {
+    loop {
+        if let Some(i) = find(&input.buf, delimiter) {
+            sink(&input.buf[..i])?;
+            input.buf.drain(..i + delimiter.len());
+            return Ok(());
+        }
+        let keep = delimiter.len() - 1;
+        if input.buf.len() > keep {
+            let end = input.buf.len() - keep;
+            sink(&input.buf[..end])?;
+            input.buf.drain(..end);
+        }
+        if !input.fill()? {
+            return Err(MultipartError::Malformed("unexpected end of a part"));
+        }
+    }
+}
+
+struct FileSink {
+    file: NamedTempFile,
+    size: u64,
+    limit: u64,
+}
+
+impl FileSink {
+    fn write(&mut self, chunk: &[u8]) -> Result<(), MultipartError> {
+        self.size += chunk.len() as u64;
+        if self.size > self.limit {
+            // The names are filled in by the caller.
+            return Err(MultipartError::FileTooLarge {
+                field_name: String::new(),
+                file_name: String::new(),
+                limit: self.limit,
+            });
+        }
+        self.file.write_all(chunk)?;
+        Ok(())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Some browsers send the full client path as the file name.
+fn base_name(file_name: &str) -> &str {
+    file_name.rsplit(['/', '\\']).next().unwrap_or(file_name)
+}
+
+/// Split a header value eg `form-data; name="a"; filename="b.txt"` into the value and
+/// its parameters. Quoted parameter values are unquoted.
+fn split_header_value(header: &str) -> (&str, Vec<(String, String)>) {
+    let (value, mut rest) = match header.split_once(';') {
+        Some((value, rest)) => (value.trim(), rest),
+        None => (header.trim(), ""),
+    };
+    let mut params = Vec::new();
+    while let Some((name, tail)) = rest.split_once('=') {
+        let name = name.trim().to_string();
+        let tail = tail.trim_start();
+        let (param_value, next) = match tail.strip_prefix('"') {
+            Some(quoted) => {
+                let mut param_value = String::new();
+                let mut chars = quoted.char_indices().peekable();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        // Only an escaped quote, IE sends "C:\dir\a.txt" unescaped.
+                        '\\' if chars.peek().is_some_and(|(_, next)| *next == '"') => {
+                            param_value.push('"');
+                            chars.next();
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => param_value.push(c),
+                    }
+                }
+                let next = quoted[end..].split_once(';').map_or("", |(_, next)| next);
+                (param_value, next)
+            }
+            None => match tail.split_once(';') {
+                Some((param_value, next)) => (param_value.trim().to_string(), next),
+                None => (tail.trim().to_string(), ""),
+            },
+        };
+        params.push((name, param_value));
+        rest = next;
+    }
+    (value, params)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::{MultipartError, MultipartSettings, boundary, parse};
+
+    const BOUNDARY: &str = "----WebKitFormBoundary7MA4YWxk";
+
+    fn body(parts: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = b"preamble\r\n".to_vec();
+        for (name, file_name, content) in parts {
+            body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+            match file_name {
+                Some(file_name) => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: text/plain\r\n\r\n",
+                        name, file_name
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend_from_slice(
+                    format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name)
+                        .as_bytes(),
+                ),
+            }
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        body
+    }
+
+    /// Hand out the body a few bytes at a time, boundaries straddle the reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(7);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    pub fn boundary_test() {
+        assert_eq!(
+            Some("abc".to_string()),
+            boundary("multipart/form-data; boundary=abc")
+        );
+        assert_eq!(
+            Some("a b;c".to_string()),
+            boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b;c\"")
+        );
+        assert_eq!(None, boundary("multipart/form-data"));
+        assert_eq!(None, boundary("application/x-www-form-urlencoded"));
+    }
+
+    #[test]
+    pub fn parse_test() {
+        let content: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+        let body = body(&[
+            ("title", None, "Grüße\r\n--almost".as_bytes()),
+            ("doc", Some("C:\\Users\\me\\notes.txt"), &content),
+            ("doc", Some("b.txt"), b""),
+            ("empty", Some(""), b""),
+            ("tag", None, b"a"),
+            ("tag", None, b"b"),
+        ]);
+        let settings = MultipartSettings::default();
+        let mut params = parse(Trickle(&body), BOUNDARY, &settings).unwrap();
+
+        assert_eq!(
+            Some("Grüße\r\n--almost"),
+            params.get_parameter_value("title")
+        );
+        assert_eq!(
+            vec!["a", "b"],
+            params.get_parameter_values("tag").collect::<Vec<_>>()
+        );
+        assert_eq!(2, params.get_files("doc").count());
+        assert!(params.get_file("empty").is_none());
+
+        let file = params.get_file("doc").unwrap();
+        assert_eq!("notes.txt", file.file_name);
+        assert_eq!(Some("text/plain"), file.content_type.as_deref());
+        assert_eq!(content.len() as u64, file.size);
+        let mut uploaded = Vec::new();
+        file.open().unwrap().read_to_end(&mut uploaded).unwrap();
+        assert_eq!(content, uploaded);
+
+        // The temporary files are deleted with the upload.
+        let path = file.path().to_path_buf();
+        assert!(path.exists());
+        drop(params.take_files());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    pub fn limit_test() {
+        let body = body(&[("doc", Some("big.bin"), &[7u8; 2000])]);
+        let settings = MultipartSettings {
+            max_file_size: 1000,
+            ..Default::default()
+        };
+        match parse(body.as_slice(), BOUNDARY, &settings) {
+            Err(MultipartError::FileTooLarge {
+                field_name,
+                file_name,
+                limit,
+            }) => assert_eq!(("doc", "big.bin", 1000), (&*field_name, &*file_name, limit)),
+            _ => panic!("expected FileTooLarge"),
+        }
+
+        let settings = MultipartSettings {
+            max_request_size: 1000,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse(body.as_slice(), BOUNDARY, &settings),
+            Err(MultipartError::RequestTooLarge { limit: 1000 })
+        ));
+    }
+
+    #[test]
+    pub fn malformed_test() {
+        let settings = MultipartSettings::default();
+        let complete = body(&[("a", None, b"1")]);
+        let truncated = &complete[..complete.len() - 20];
+        for invalid in [
+            b"no boundary at all".as_slice(),
+            truncated,
+            format!(
+                "--{}\r\nContent-Type: text/plain\r\n\r\nx\r\n--{}--",
+                BOUNDARY, BOUNDARY
+            )
+            .as_bytes(),
+            format!("--{}garbage", BOUNDARY).as_bytes(),
+        ] {
+            assert!(
+                matches!(
+                    parse(invalid, BOUNDARY, &settings),
+                    Err(MultipartError::Malformed(_))
+                ),
+                "{}",
+                String::from_utf8_lossy(invalid)
+            );
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::Display;
+
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+
+/// Characters escaped in a path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// A url relative to the application root, as produced and consumed by the request mappers.
+/// Segments and query parameters are held decoded, Display encodes them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Url {
+    pub segments: Vec<String>,
+    pub query_parameters: Vec<QueryParameter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParameter {
+    pub name: String,
+    pub value: String,
+}
+
+impl Url {
+    /// An empty url.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a request target path and query eg "/product/a%20b?tab=1".
+    /// Empty segments are dropped, "/a//b/" has the segments "a" and "b".
+    pub fn parse(path_and_query: &str) -> Self {
+        let (path, query) = match path_and_query.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path_and_query, None),
+        };
+
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+            .collect();
+
+        let query_parameters = query
+            .map(|q| {
+                form_urlencoded::parse(q.as_bytes())
+                    .map(|(name, value)| QueryParameter {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            segments,
+            query_parameters,
+        }
+    }
+
+    /// Append a path segment.
+    pub fn add_segment(mut self, segment: impl Into<String>) -> Self {
+        self.segments.push(segment.into());
+        self
+    }
+
+    /// Append a query parameter.
+    pub fn add_query_parameter(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.query_parameters.push(QueryParameter {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// The first query parameter with the name.
+    pub fn get_query_parameter(&self, name: &str) -> Option<&QueryParameter> {
+        self.query_parameters.iter().find(|p| p.name == name)
+    }
+
+    /// The encoded path, always starting with '/'.
+    pub fn get_path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.segments {
+            path.push('/');
+            path.extend(utf8_percent_encode(segment, SEGMENT));
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+
+    /// The encoded query string without the leading '?'.
+    /// Parameters with an empty value are written without the '=' eg the page info "?3-1".
+    pub fn get_query_string(&self) -> Option<String> {
+        if self.query_parameters.is_empty() {
+            return None;
+        }
+        let mut query = String::new();
+        for param in &self.query_parameters {
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.extend(form_urlencoded::byte_serialize(param.name.as_bytes()));
+            if !param.value.is_empty() {
+                query.push('=');
+                query.extend(form_urlencoded::byte_serialize(param.value.as_bytes()));
+            }
+        }
+        Some(query)
+    }
+}
+
+impl Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_path())?;
+        if let Some(query) = self.get_query_string() {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Url;
+
+    #[test]
+    pub fn parse_test() {
+        let url = Url::parse("/product//a%20b/?tab=x+y&tab=2&q=%26");
+        assert_eq!(vec!["product", "a b"], url.segments);
+        assert_eq!(3, url.query_parameters.len());
+        assert_eq!("x y", url.get_query_parameter("tab").unwrap().value);
+        assert_eq!("&", url.get_query_parameter("q").unwrap().value);
+
+        let url = Url::parse("/");
+        assert!(url.segments.is_empty());
+        assert!(url.query_parameters.is_empty());
+    }
+
+    #[test]
+    pub fn display_test() {
+        assert_eq!("/", Url::new().to_string());
+        let url = Url::new()
+            .add_segment("product")
+            .add_segment("a b/c")
+            .add_query_parameter("q", "x&y z");
+        assert_eq!("/product/a%20b%2Fc?q=x%26y+z", url.to_string());
+        assert_eq!(url, Url::parse(&url.to_string()));
+
+        let url = Url::new().add_segment("a").add_query_parameter("3-1", "");
+        assert_eq!("/a?3-1", url.to_string());
+        assert_eq!(url, Url::parse(&url.to_string()));
+    }
+}
@@ -16,6 +16,65 @@ pub mod constants {
 
         /// Client-side Resources
         pub const JS: &str = "js";
+        pub const MJS: &str = "mjs";
         pub const CSS: &str = "css";
+        pub const MAP: &str = "map";
+        pub const TXT: &str = "txt";
+        pub const PDF: &str = "pdf";
+        pub const WASM: &str = "wasm";
+
+        /// Images
+        pub const PNG: &str = "png";
+        pub const JPG: &str = "jpg";
+        pub const JPEG: &str = "jpeg";
+        pub const GIF: &str = "gif";
+        pub const SVG: &str = "svg";
+        pub const ICO: &str = "ico";
+        pub const WEBP: &str = "webp";
+        pub const AVIF: &str = "avif";
+
+        /// Fonts
+        pub const WOFF: &str = "woff";
+        pub const WOFF2: &str = "woff2";
+        pub const TTF: &str = "ttf";
+        pub const OTF: &str = "otf";
+
+        /// Audio and video
+        pub const MP3: &str = "mp3";
+        pub const MP4: &str = "mp4";
+        pub const OGG: &str = "ogg";
+        pub const WEBM: &str = "webm";
+
+        /// The Content-Type served for a file extension, case insensitive.
+        pub fn content_type(extension: &str) -> Option<&'static str> {
+            let content_type = match extension.to_ascii_lowercase().as_str() {
+                HTML => "text/html; charset=utf-8",
+                XHTML => "application/xhtml+xml; charset=utf-8",
+                XML => "application/xml; charset=utf-8",
+                PROPERTIES | TXT => "text/plain; charset=utf-8",
+                JSON | MAP => "application/json",
+                JS | MJS => "text/javascript; charset=utf-8",
+                CSS => "text/css; charset=utf-8",
+                PDF => "application/pdf",
+                WASM => "application/wasm",
+                PNG => "image/png",
+                JPG | JPEG => "image/jpeg",
+                GIF => "image/gif",
+                SVG => "image/svg+xml",
+                ICO => "image/x-icon",
+                WEBP => "image/webp",
+                AVIF => "image/avif",
+                WOFF => "font/woff",
+                WOFF2 => "font/woff2",
+                TTF => "font/ttf",
+                OTF => "font/otf",
+                MP3 => "audio/mpeg",
+                MP4 => "video/mp4",
+                OGG => "audio/ogg",
+                WEBM => "video/webm",
+                _ => return None,
+            };
+            Some(content_type)
+        }
     }
 }